@@ -0,0 +1,227 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use chrono::TimeZone;
+use tokio::sync::{mpsc, oneshot, Mutex};
+use walle_core::{
+    event::Event,
+    resp::{resp_error, RespError},
+    util::{Value, ValueMap},
+};
+
+use crate::{
+    file::{download_capped, sha256_hex, url_file_name, write_atomic},
+    ArchiveConfig,
+};
+
+const INDEX_FILE: &str = "index.json";
+const MEDIA_TYPES: [&str; 4] = ["image", "video", "file", "audio"];
+
+/// one media file waiting for download
+struct Job {
+    dir: PathBuf,
+    url: String,
+    name: String,
+    done: oneshot::Sender<PathBuf>,
+}
+
+/// keeps a local copy of media posted in configured guilds and channels,
+/// laid out as `dir/guild_id/channel_id/date/name`,
+/// downloads run on workers so event delivery is never blocked by them
+pub struct Archiver {
+    config: ArchiveConfig,
+    queue: mpsc::Sender<Job>,
+    /// archived content sha256 to its first archived path
+    hashes: Mutex<HashMap<String, PathBuf>>,
+    /// asset url to archived content sha256
+    urls: Mutex<HashMap<String, String>>,
+}
+
+impl Archiver {
+    pub async fn new(config: ArchiveConfig) -> Arc<Self> {
+        let hashes = match tokio::fs::read(config.dir.join(INDEX_FILE)).await {
+            Ok(data) => serde_json::from_slice(&data).unwrap_or_else(|e| {
+                tracing::warn!(target: crate::WALLE_K, "load archive index failed:{}", e);
+                HashMap::default()
+            }),
+            Err(_) => HashMap::default(),
+        };
+        let (queue, jobs) = mpsc::channel(config.queue_size.max(1));
+        let jobs = Arc::new(Mutex::new(jobs));
+        let archiver = Arc::new(Self {
+            config,
+            queue,
+            hashes: Mutex::new(hashes),
+            urls: Mutex::default(),
+        });
+        for _ in 0..archiver.config.workers.max(1) {
+            tokio::spawn(archiver.clone().work(jobs.clone()));
+        }
+        archiver
+    }
+
+    async fn work(self: Arc<Self>, jobs: Arc<Mutex<mpsc::Receiver<Job>>>) {
+        let timeout = Duration::from_secs(self.config.timeout);
+        loop {
+            let Some(job) = jobs.lock().await.recv().await else {
+                return;
+            };
+            match tokio::time::timeout(timeout, self.archive(&job.dir, &job.url, &job.name)).await {
+                Ok(Ok(path)) => {
+                    job.done.send(path).ok();
+                }
+                Ok(Err(e)) => {
+                    tracing::warn!(target: crate::WALLE_K, "archive media failed:{}", e.message)
+                }
+                Err(_) => {
+                    tracing::warn!(target: crate::WALLE_K, "archive media {} timeout", job.url)
+                }
+            }
+        }
+    }
+
+    fn should_archive(&self, guild_id: &str, channel_id: &str) -> bool {
+        self.config.guilds.iter().any(|g| g == guild_id)
+            || self.config.channels.iter().any(|c| c == channel_id)
+    }
+
+    /// queue media segments of a channel message event for archiving, and add
+    /// `kook.local_path` to segments archived within the configured wait
+    pub async fn archive_event(&self, event: &mut Event) {
+        if event.ty != "message" || event.detail_type != "channel" {
+            return;
+        }
+        let (Some(Value::Str(guild_id)), Some(Value::Str(channel_id))) = (
+            event.extra.get("guild_id").cloned(),
+            event.extra.get("channel_id").cloned(),
+        ) else {
+            return;
+        };
+        if !self.should_archive(&guild_id, &channel_id) {
+            return;
+        }
+        let date = chrono::Local
+            .timestamp_millis_opt(event.time as i64)
+            .single()
+            .unwrap_or_else(chrono::Local::now)
+            .format("%Y-%m-%d")
+            .to_string();
+        let dir = self.config.dir.join(guild_id).join(channel_id).join(date);
+        let Some(Value::List(segments)) = event.extra.get_mut("message") else {
+            return;
+        };
+        let deadline = tokio::time::Instant::now() + Duration::from_millis(self.config.wait);
+        let mut pending = vec![];
+        for seg in segments.iter_mut() {
+            let Value::Map(seg) = seg else { continue };
+            if !matches!(seg.get("type"), Some(Value::Str(ty)) if MEDIA_TYPES.contains(&ty.as_str()))
+            {
+                continue;
+            }
+            let Some(Value::Map(data)) = seg.get_mut("data") else {
+                continue;
+            };
+            let Some(Value::Str(url)) = data.get("url").cloned() else {
+                continue;
+            };
+            let name = match data.get("name") {
+                Some(Value::Str(name)) => name.clone(),
+                _ => url_file_name(&url),
+            };
+            let (done, rx) = oneshot::channel();
+            let job = Job {
+                dir: dir.clone(),
+                url,
+                name,
+                done,
+            };
+            match self.queue.try_send(job) {
+                Ok(()) => pending.push((data, rx)),
+                Err(e) => tracing::warn!(
+                    target: crate::WALLE_K,
+                    "archive queue full, skip {}",
+                    e.into_inner().url
+                ),
+            }
+        }
+        if self.config.wait == 0 {
+            return;
+        }
+        for (data, rx) in pending {
+            if let Ok(Ok(path)) = tokio::time::timeout_at(deadline, rx).await {
+                insert_local_path(data, &path);
+            }
+        }
+    }
+
+    async fn archive(&self, dir: &Path, url: &str, name: &str) -> Result<PathBuf, RespError> {
+        let known = self.urls.lock().await.get(url).cloned();
+        let (sha256, content) = match known {
+            Some(sha256) => (sha256, None),
+            None => {
+                let content = download_capped(url, self.config.max_size).await?;
+                (sha256_hex(&content), Some(content))
+            }
+        };
+        // prefix with the hash so equal names of different content don't collide
+        let path = dir.join(format!("{}-{}", &sha256[..8], sanitize(name)));
+        if tokio::fs::try_exists(&path).await.unwrap_or(false) {
+            return Ok(path);
+        }
+        tokio::fs::create_dir_all(dir)
+            .await
+            .map_err(resp_error::filesystem_error)?;
+        let existing = self.hashes.lock().await.get(&sha256).cloned();
+        let linked = match existing {
+            Some(ref src) => tokio::fs::hard_link(src, &path).await.is_ok(),
+            None => false,
+        };
+        if !linked {
+            let content = match (content, existing) {
+                (Some(content), _) => content,
+                (None, Some(src)) => tokio::fs::read(src)
+                    .await
+                    .map_err(resp_error::filesystem_error)?,
+                (None, None) => download_capped(url, self.config.max_size).await?,
+            };
+            write_atomic(&path, &content)
+                .await
+                .map_err(resp_error::filesystem_error)?;
+        }
+        self.urls
+            .lock()
+            .await
+            .insert(url.to_owned(), sha256.clone());
+        // written under the lock, so a stale index never overwrites a newer one
+        let mut hashes = self.hashes.lock().await;
+        if !hashes.contains_key(&sha256) {
+            hashes.insert(sha256, path.clone());
+            let index = serde_json::to_vec(&*hashes).map_err(resp_error::filesystem_error)?;
+            write_atomic(&self.config.dir.join(INDEX_FILE), &index)
+                .await
+                .map_err(resp_error::filesystem_error)?;
+        }
+        Ok(path)
+    }
+}
+
+fn insert_local_path(data: &mut ValueMap, path: &Path) {
+    data.insert(
+        "kook.local_path".to_owned(),
+        path.to_string_lossy().into_owned().into(),
+    );
+}
+
+/// keep file names within their directory
+fn sanitize(name: &str) -> String {
+    name.chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '\0' => '_',
+            c => c,
+        })
+        .collect()
+}
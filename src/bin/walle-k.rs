@@ -56,7 +56,9 @@ async fn main() {
             KHandler::default(),
             ImplOBC::new(walle_k::WALLE_K.to_owned()),
         ));
-        ob.start(config.kook, config.onebot, true).await.unwrap();
+        ob.start(config.handler_config(), config.onebot, true)
+            .await
+            .unwrap();
         ob.wait_all().await;
     }
 }
@@ -1,4 +1,8 @@
-use std::io::{Read, Write};
+use std::{
+    io::{Read, Write},
+    net::{IpAddr, Ipv4Addr},
+    path::PathBuf,
+};
 
 pub use kook::prelude::Config as KookConfig;
 use serde::{Deserialize, Serialize};
@@ -11,9 +15,180 @@ const CONFIG_PATH: &str = "walle-k.toml";
 pub struct Config {
     pub meta: MetaConfig,
     pub kook: KookConfig,
+    #[serde(default)]
+    pub file: FileConfig,
+    #[serde(default)]
+    pub archive: ArchiveConfig,
+    #[serde(default)]
+    pub event: EventConfig,
+    #[serde(default)]
+    pub webhook: WebhookConfig,
     pub onebot: ImplConfig,
 }
 
+impl Config {
+    pub fn handler_config(&self) -> HandlerConfig {
+        HandlerConfig {
+            kook: self.kook.clone(),
+            file: self.file.clone(),
+            archive: self.archive.clone(),
+            event: self.event.clone(),
+            webhook: self.webhook.clone(),
+        }
+    }
+}
+
+/// config for `KHandler::start`
+#[derive(Debug, Clone, Default)]
+pub struct HandlerConfig {
+    pub kook: KookConfig,
+    pub file: FileConfig,
+    pub archive: ArchiveConfig,
+    pub event: EventConfig,
+    pub webhook: WebhookConfig,
+}
+
+/// receive events through Kook webhook callbacks instead of the websocket gateway
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct WebhookConfig {
+    pub enable: bool,
+    pub host: IpAddr,
+    pub port: u16,
+    /// request path of the callback url
+    pub path: String,
+    /// `Verify Token` from Kook developer console, required when enabled
+    pub verify_token: String,
+    /// `Encrypt Key` from Kook developer console, callbacks must be encrypted when set
+    pub encrypt_key: String,
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        Self {
+            enable: false,
+            host: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            port: 8090,
+            path: "/kook".to_owned(),
+            verify_token: String::default(),
+            encrypt_key: String::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct EventConfig {
+    /// events kept for `get_latest_events`, 0 to disable
+    pub buffer_size: usize,
+    /// seconds a received event is remembered to drop redeliveries, 0 to disable
+    pub dedup_window: u64,
+    /// max events remembered for de-duplication
+    pub dedup_size: usize,
+}
+
+impl Default for EventConfig {
+    fn default() -> Self {
+        Self {
+            buffer_size: 1000,
+            dedup_window: 600,
+            dedup_size: 10000,
+        }
+    }
+}
+
+/// archive media posted in listed guilds or channels
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ArchiveConfig {
+    pub enable: bool,
+    pub dir: PathBuf,
+    pub guilds: Vec<String>,
+    pub channels: Vec<String>,
+    /// concurrent downloads
+    pub workers: usize,
+    /// media waiting for download, more are skipped
+    pub queue_size: usize,
+    /// seconds allowed for one download
+    pub timeout: u64,
+    /// max bytes of one media file, 0 for unlimited
+    pub max_size: u64,
+    /// milliseconds an event waits for its media to add `kook.local_path`, 0 to never wait
+    pub wait: u64,
+}
+
+impl Default for ArchiveConfig {
+    fn default() -> Self {
+        Self {
+            enable: false,
+            dir: PathBuf::from("archive"),
+            guilds: vec![],
+            channels: vec![],
+            workers: 2,
+            queue_size: 100,
+            timeout: 300,
+            max_size: 100 * 1024 * 1024,
+            wait: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct FileConfig {
+    /// directory for staged uploads and cached files
+    pub cache_dir: PathBuf,
+    /// seconds before an unfinished fragmented upload is discarded
+    pub fragment_timeout: u64,
+    /// max bytes of files cached for `get_file`, 0 for unlimited
+    pub cache_max_size: u64,
+    /// seconds a file cached for `get_file` is kept, 0 for unlimited
+    pub cache_max_age: u64,
+    /// records file of uploaded assets, default to `assets.json` in cache_dir
+    pub asset_cache_file: Option<PathBuf>,
+    /// max uploaded assets to remember, 0 for unlimited
+    pub asset_cache_size: usize,
+    /// directories media `path` may be read from, local paths are rejected when empty
+    pub allowed_dirs: Vec<PathBuf>,
+    /// download media urls not hosted by Kook and upload them to Kook asset host
+    pub reupload_external: bool,
+    /// max bytes of a file to upload, 0 for unlimited
+    pub max_upload_size: u64,
+    /// recompress images larger than this many bytes, 0 to disable
+    pub image_compress_threshold: u64,
+    /// downscale images wider or higher than this many pixels, 0 to disable
+    pub image_max_dimension: u32,
+    /// jpeg quality used when recompressing images
+    pub image_quality: u8,
+}
+
+impl FileConfig {
+    pub fn asset_cache_file(&self) -> PathBuf {
+        self.asset_cache_file
+            .clone()
+            .unwrap_or_else(|| self.cache_dir.join("assets.json"))
+    }
+}
+
+impl Default for FileConfig {
+    fn default() -> Self {
+        Self {
+            cache_dir: PathBuf::from("cache"),
+            fragment_timeout: 600,
+            cache_max_size: 1024 * 1024 * 1024,
+            cache_max_age: 7 * 24 * 60 * 60,
+            asset_cache_file: None,
+            asset_cache_size: 10000,
+            allowed_dirs: vec![],
+            reupload_external: false,
+            max_upload_size: 30 * 1024 * 1024,
+            image_compress_threshold: 0,
+            image_max_dimension: 0,
+            image_quality: 85,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize, Default)]
 pub struct MetaConfig {
     pub log_level: LevelRef,
@@ -0,0 +1,130 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+#[derive(Default)]
+struct DedupInner {
+    /// key to the time it was last seen
+    seen: HashMap<String, Instant>,
+    /// keys in arrival order, oldest first
+    order: VecDeque<(Instant, String)>,
+}
+
+impl DedupInner {
+    fn pop_oldest(&mut self) {
+        if let Some((time, key)) = self.order.pop_front() {
+            if self.seen.get(&key) == Some(&time) {
+                self.seen.remove(&key);
+            }
+        }
+    }
+}
+
+/// drops events redelivered within a time window, such as replays
+/// after resuming a session or retried webhook callbacks
+pub struct Dedup {
+    window: Duration,
+    capacity: usize,
+    inner: Mutex<DedupInner>,
+    discarded: AtomicU64,
+}
+
+impl Dedup {
+    pub fn new(window: Duration, capacity: usize) -> Self {
+        Self {
+            window,
+            capacity,
+            inner: Mutex::default(),
+            discarded: AtomicU64::default(),
+        }
+    }
+
+    /// record the key, returns false when it was already seen within the window
+    pub fn check(&self, key: String) -> bool {
+        if self.window.is_zero() || self.capacity == 0 {
+            return true;
+        }
+        let now = Instant::now();
+        let mut inner = self.inner.lock().unwrap();
+        while let Some((time, _)) = inner.order.front() {
+            if now.duration_since(*time) <= self.window {
+                break;
+            }
+            inner.pop_oldest();
+        }
+        if inner.seen.contains_key(&key) {
+            let discarded = self.discarded.fetch_add(1, Ordering::Relaxed) + 1;
+            tracing::debug!(
+                target: crate::WALLE_K,
+                "duplicate Kook event {} discarded, {} in total",
+                key,
+                discarded
+            );
+            return false;
+        }
+        while inner.order.len() >= self.capacity {
+            inner.pop_oldest();
+        }
+        inner.seen.insert(key.clone(), now);
+        inner.order.push_back((now, key));
+        true
+    }
+
+    /// duplicates discarded since start
+    pub fn discarded(&self) -> u64 {
+        self.discarded.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn duplicates_counted() {
+        let dedup = Dedup::new(Duration::from_secs(60), 10);
+        assert!(dedup.check("a".to_owned()));
+        assert!(dedup.check("b".to_owned()));
+        assert!(!dedup.check("a".to_owned()));
+        assert!(!dedup.check("a".to_owned()));
+        assert_eq!(dedup.discarded(), 2);
+    }
+
+    #[test]
+    fn window_expiry() {
+        let dedup = Dedup::new(Duration::from_millis(20), 10);
+        assert!(dedup.check("a".to_owned()));
+        std::thread::sleep(Duration::from_millis(40));
+        assert!(dedup.check("a".to_owned()));
+        assert!(!dedup.check("a".to_owned()));
+    }
+
+    #[test]
+    fn capacity_eviction() {
+        let dedup = Dedup::new(Duration::from_secs(60), 2);
+        assert!(dedup.check("a".to_owned()));
+        assert!(dedup.check("b".to_owned()));
+        // evicts "a"
+        assert!(dedup.check("c".to_owned()));
+        assert!(dedup.check("a".to_owned()));
+        // a duplicate evicts nothing, "b" was evicted by "a"
+        assert!(!dedup.check("c".to_owned()));
+        assert!(dedup.check("b".to_owned()));
+        assert!(!dedup.check("a".to_owned()));
+        let inner = dedup.inner.lock().unwrap();
+        assert!(inner.seen.len() <= 2 && inner.order.len() <= 2);
+    }
+
+    #[test]
+    fn disabled() {
+        let dedup = Dedup::new(Duration::ZERO, 10);
+        assert!(dedup.check("a".to_owned()));
+        assert!(dedup.check("a".to_owned()));
+        assert_eq!(dedup.discarded(), 0);
+    }
+}
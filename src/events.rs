@@ -0,0 +1,192 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+use tokio::sync::{Mutex, Notify};
+use walle_core::event::Event;
+
+/// longest long-poll wait in seconds
+const MAX_TIMEOUT: i64 = 300;
+/// consumers tracked at most, the least recently seen is forgotten first
+const MAX_CONSUMERS: usize = 1024;
+/// consumers not seen for this long are forgotten
+const CONSUMER_IDLE: Duration = Duration::from_secs(3600);
+
+#[derive(Default)]
+struct BufferInner {
+    /// events with their sequence number, oldest first
+    events: VecDeque<(u64, Event)>,
+    next_seq: u64,
+    /// next sequence number to deliver and last seen time of each consumer
+    cursors: HashMap<String, (u64, Instant)>,
+}
+
+impl BufferInner {
+    fn set_cursor(&mut self, consumer: &str, seq: u64) {
+        let now = Instant::now();
+        self.cursors
+            .retain(|_, (_, seen)| now.duration_since(*seen) < CONSUMER_IDLE);
+        if !self.cursors.contains_key(consumer) && self.cursors.len() >= MAX_CONSUMERS {
+            let oldest = self
+                .cursors
+                .iter()
+                .min_by_key(|(_, (_, seen))| *seen)
+                .map(|(c, _)| c.clone());
+            if let Some(oldest) = oldest {
+                self.cursors.remove(&oldest);
+            }
+        }
+        self.cursors.insert(consumer.to_owned(), (seq, now));
+    }
+}
+
+/// bounded buffer of parsed events for `get_latest_events`,
+/// every consumer receives each buffered event exactly once
+pub struct EventBuffer {
+    capacity: usize,
+    inner: Mutex<BufferInner>,
+    notify: Notify,
+}
+
+impl EventBuffer {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            inner: Mutex::default(),
+            notify: Notify::new(),
+        }
+    }
+
+    pub async fn push(&self, event: Event) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut inner = self.inner.lock().await;
+        let seq = inner.next_seq;
+        inner.next_seq += 1;
+        inner.events.push_back((seq, event));
+        while inner.events.len() > self.capacity {
+            inner.events.pop_front();
+        }
+        drop(inner);
+        self.notify.notify_waiters();
+    }
+
+    /// events not yet delivered to consumer, at most `limit` (<= 0 for all),
+    /// wait up to `timeout` seconds (at most 300) for new events when there is none
+    pub async fn take(&self, consumer: &str, limit: i64, timeout: i64) -> Vec<Event> {
+        let timeout = Duration::from_secs(timeout.clamp(0, MAX_TIMEOUT) as u64);
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            let notified = self.notify.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+            {
+                let mut inner = self.inner.lock().await;
+                let oldest = inner
+                    .events
+                    .front()
+                    .map(|(seq, _)| *seq)
+                    .unwrap_or(inner.next_seq);
+                let cursor = inner
+                    .cursors
+                    .get(consumer)
+                    .map(|(seq, _)| *seq)
+                    .unwrap_or(oldest)
+                    .max(oldest);
+                let events: Vec<(u64, Event)> = inner
+                    .events
+                    .iter()
+                    .filter(|(seq, _)| *seq >= cursor)
+                    .take(if limit > 0 {
+                        limit as usize
+                    } else {
+                        usize::MAX
+                    })
+                    .cloned()
+                    .collect();
+                if let Some((last, _)) = events.last() {
+                    inner.set_cursor(consumer, last + 1);
+                    return events.into_iter().map(|(_, e)| e).collect();
+                }
+                inner.set_cursor(consumer, cursor);
+            }
+            if tokio::time::timeout_at(deadline, notified).await.is_err() {
+                return vec![];
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(id: u64) -> Event {
+        Event {
+            id: id.to_string(),
+            time: 0.0,
+            ty: "meta".to_owned(),
+            detail_type: "test".to_owned(),
+            sub_type: "".to_owned(),
+            extra: Default::default(),
+        }
+    }
+
+    fn ids(events: Vec<Event>) -> Vec<String> {
+        events.into_iter().map(|e| e.id).collect()
+    }
+
+    #[tokio::test]
+    async fn cursors_per_consumer() {
+        let buffer = EventBuffer::new(10);
+        for i in 0..3 {
+            buffer.push(event(i)).await;
+        }
+        assert_eq!(ids(buffer.take("a", 2, 0).await), ["0", "1"]);
+        assert_eq!(ids(buffer.take("a", 0, 0).await), ["2"]);
+        assert!(buffer.take("a", 0, 0).await.is_empty());
+        assert_eq!(ids(buffer.take("b", 0, 0).await), ["0", "1", "2"]);
+    }
+
+    #[tokio::test]
+    async fn evicted_events_are_skipped() {
+        let buffer = EventBuffer::new(2);
+        buffer.push(event(0)).await;
+        assert_eq!(ids(buffer.take("a", 0, 0).await), ["0"]);
+        for i in 1..5 {
+            buffer.push(event(i)).await;
+        }
+        // cursor of "a" fell behind the oldest buffered event
+        assert_eq!(ids(buffer.take("a", 0, 0).await), ["3", "4"]);
+        assert_eq!(ids(buffer.take("b", 1, 0).await), ["3"]);
+    }
+
+    #[tokio::test]
+    async fn disabled_buffer() {
+        let buffer = EventBuffer::new(0);
+        buffer.push(event(0)).await;
+        assert!(buffer.take("a", 0, 0).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn long_poll_with_huge_timeout() {
+        let buffer = std::sync::Arc::new(EventBuffer::new(10));
+        let pusher = buffer.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            pusher.push(event(0)).await;
+        });
+        assert_eq!(ids(buffer.take("a", 0, i64::MAX).await), ["0"]);
+    }
+
+    #[tokio::test]
+    async fn consumers_are_bounded() {
+        let buffer = EventBuffer::new(10);
+        for i in 0..MAX_CONSUMERS + 10 {
+            buffer.take(&i.to_string(), 0, 0).await;
+        }
+        assert_eq!(buffer.inner.lock().await.cursors.len(), MAX_CONSUMERS);
+    }
+}
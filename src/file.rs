@@ -0,0 +1,719 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::SeekFrom,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        RwLock,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::{
+    io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt},
+    sync::Mutex,
+};
+use walle_core::resp::{resp_error, RespError};
+
+/// retcode for files larger than `max_upload_size`
+pub const FILE_TOO_LARGE: u32 = 32001;
+/// retcode for files refused by Kook asset host
+pub const FILE_REJECTED: u32 = 32002;
+
+pub fn file_error<S: ToString>(retcode: u32, message: S) -> RespError {
+    RespError {
+        retcode,
+        message: message.to_string(),
+    }
+}
+
+/// file uploaded to Kook asset host
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct FileInfo {
+    pub name: String,
+    pub url: String,
+    pub sha256: String,
+    /// unix timestamp of last upload or cache hit
+    #[serde(default)]
+    pub last_used: u64,
+}
+
+#[derive(Default)]
+struct StoreInner {
+    files: HashMap<String, FileInfo>,
+    path: Option<PathBuf>,
+    /// 0 for unlimited
+    capacity: usize,
+}
+
+/// uploaded files indexed by file_id (sha256 of the content),
+/// persisted so repeated content skips uploading
+#[derive(Default)]
+pub struct FileStore {
+    inner: RwLock<StoreInner>,
+    /// held while persisting, so a stale snapshot never overwrites a newer one
+    save: Mutex<()>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+fn now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+impl FileStore {
+    /// load persisted records, a missing or broken file starts an empty store
+    pub async fn load(&self, path: PathBuf, capacity: usize) {
+        let files = match tokio::fs::read(&path).await {
+            Ok(data) => match serde_json::from_slice::<Vec<FileInfo>>(&data) {
+                Ok(files) => files,
+                Err(e) => {
+                    tracing::warn!(target: crate::WALLE_K, "load asset cache failed:{}", e);
+                    vec![]
+                }
+            },
+            Err(_) => vec![],
+        };
+        tracing::info!(
+            target: crate::WALLE_K,
+            "loaded {} cached assets from {}",
+            files.len(),
+            path.display()
+        );
+        let mut inner = self.inner.write().unwrap();
+        inner.files = files.into_iter().map(|f| (f.sha256.clone(), f)).collect();
+        inner.path = Some(path);
+        inner.capacity = capacity;
+    }
+
+    /// asset cache hits and misses since start
+    pub fn stats(&self) -> (u64, u64) {
+        (
+            self.hits.load(Ordering::Relaxed),
+            self.misses.load(Ordering::Relaxed),
+        )
+    }
+
+    pub fn get(&self, file_id: &str) -> Option<FileInfo> {
+        self.inner.read().unwrap().files.get(file_id).cloned()
+    }
+
+    /// look up uploaded content by sha256, counting cache hits and misses
+    pub fn lookup(&self, sha256: &str) -> Option<FileInfo> {
+        let found = self.inner.write().unwrap().files.get_mut(sha256).map(|f| {
+            f.last_used = now();
+            f.clone()
+        });
+        let (hits, misses) = if found.is_some() {
+            (
+                self.hits.fetch_add(1, Ordering::Relaxed) + 1,
+                self.misses.load(Ordering::Relaxed),
+            )
+        } else {
+            (
+                self.hits.load(Ordering::Relaxed),
+                self.misses.fetch_add(1, Ordering::Relaxed) + 1,
+            )
+        };
+        tracing::debug!(
+            target: crate::WALLE_K,
+            "asset cache {} {} (hits:{} misses:{})",
+            if found.is_some() { "hit" } else { "miss" },
+            sha256,
+            hits,
+            misses
+        );
+        found
+    }
+
+    pub async fn insert(&self, mut info: FileInfo) -> String {
+        let file_id = info.sha256.clone();
+        info.last_used = now();
+        {
+            let mut inner = self.inner.write().unwrap();
+            inner.files.insert(file_id.clone(), info);
+            if inner.capacity > 0 && inner.files.len() > inner.capacity {
+                let mut used: Vec<(u64, String)> = inner
+                    .files
+                    .values()
+                    .map(|f| (f.last_used, f.sha256.clone()))
+                    .collect();
+                used.sort_unstable();
+                let over = inner.files.len() - inner.capacity;
+                for (_, sha256) in used.into_iter().take(over) {
+                    inner.files.remove(&sha256);
+                }
+            }
+        }
+        let _save = self.save.lock().await;
+        let persist = {
+            let inner = self.inner.read().unwrap();
+            inner.path.clone().map(|path| {
+                let files: Vec<&FileInfo> = inner.files.values().collect();
+                (path, serde_json::to_vec(&files))
+            })
+        };
+        if let Some((path, Ok(data))) = persist {
+            if let Err(e) = write_atomic(&path, &data).await {
+                tracing::warn!(target: crate::WALLE_K, "save asset cache failed:{}", e);
+            }
+        }
+        file_id
+    }
+
+    /// resolve a segment file_id to Kook asset url, ids of received segments
+    /// are asset urls already, other unknown ids were never uploaded or evicted
+    pub fn resolve(&self, file_id: String) -> Result<String, RespError> {
+        match self.get(&file_id) {
+            Some(info) => Ok(info.url),
+            None if file_id.contains("://") => Ok(file_id),
+            None => Err(resp_error::bad_segment_data(format!(
+                "unknown or expired file_id:{}",
+                file_id
+            ))),
+        }
+    }
+}
+
+/// content type from magic bytes, then from file name extension
+pub fn detect_mime(data: &[u8], name: Option<&str>) -> Option<String> {
+    if let Some(ty) = infer::get(data) {
+        return Some(ty.mime_type().to_owned());
+    }
+    name.and_then(|n| mime_guess::from_path(n).first())
+        .map(|m| m.essence_str().to_owned())
+}
+
+/// file name for upload, generated from content type when absent
+pub fn file_name(data: &[u8], name: Option<String>, kind: &str) -> String {
+    name.filter(|n| !n.is_empty())
+        .unwrap_or_else(|| match infer::get(data) {
+            Some(ty) => format!("{}.{}", kind, ty.extension()),
+            None => kind.to_owned(),
+        })
+}
+
+/// read a local file, only within allowed directories
+pub async fn read_local(path: &str, allowed_dirs: &[PathBuf]) -> Result<Vec<u8>, RespError> {
+    let real = tokio::fs::canonicalize(path)
+        .await
+        .map_err(|e| resp_error::filesystem_error(format!("{}:{}", path, e)))?;
+    let mut allowed = false;
+    for dir in allowed_dirs {
+        if let Ok(dir) = tokio::fs::canonicalize(dir).await {
+            if real.starts_with(dir) {
+                allowed = true;
+                break;
+            }
+        }
+    }
+    if !allowed {
+        return Err(resp_error::bad_param(format!(
+            "path:{} not in allowed directories",
+            path
+        )));
+    }
+    tokio::fs::read(&real)
+        .await
+        .map_err(|e| resp_error::filesystem_error(format!("{}:{}", path, e)))
+}
+
+/// decode a `data:` uri to its content type and content, None for other urls
+pub fn decode_data_uri(uri: &str) -> Result<Option<(Option<String>, Vec<u8>)>, RespError> {
+    let Some(rest) = uri.strip_prefix("data:") else {
+        return Ok(None);
+    };
+    let Some((meta, data)) = rest.split_once(',') else {
+        return Err(resp_error::bad_segment_data("invalid data uri"));
+    };
+    let (mime, base64) = match meta.strip_suffix(";base64") {
+        Some(mime) => (mime, true),
+        None => (meta, false),
+    };
+    let mime = mime.split(';').next().filter(|m| !m.is_empty());
+    let data = if base64 {
+        use base64::Engine;
+        base64::engine::general_purpose::STANDARD
+            .decode(data)
+            .map_err(|e| resp_error::bad_segment_data(format!("invalid data uri:{}", e)))?
+    } else {
+        data.as_bytes().to_vec()
+    };
+    Ok(Some((mime.map(ToOwned::to_owned), data)))
+}
+
+/// whether the url is hosted by Kook asset host
+pub fn is_kook_asset(url: &str) -> bool {
+    let Some((scheme, rest)) = url.split_once("://") else {
+        return false;
+    };
+    if !["http", "https"].contains(&scheme.to_ascii_lowercase().as_str()) {
+        return false;
+    }
+    let host = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    // userinfo and ports are never used by Kook asset urls
+    if host.contains(['@', ':']) {
+        return false;
+    }
+    let host = host.to_ascii_lowercase();
+    ["kookapp.cn", "kaiheila.cn"]
+        .iter()
+        .any(|h| host == *h || host.ends_with(&format!(".{}", h)))
+}
+
+pub fn sha256_hex(data: &[u8]) -> String {
+    hex::encode(Sha256::digest(data))
+}
+
+/// check content against the sha256 provided by client
+pub fn verify_sha256(data: &[u8], sha256: Option<&str>) -> Result<String, RespError> {
+    let digest = sha256_hex(data);
+    match sha256 {
+        Some(expected) if !expected.eq_ignore_ascii_case(&digest) => Err(resp_error::bad_param(
+            format!("sha256 mismatch, expected {} got {}", expected, digest),
+        )),
+        _ => Ok(digest),
+    }
+}
+
+pub async fn download(
+    url: &str,
+    headers: Option<&HashMap<String, String>>,
+) -> Result<Vec<u8>, RespError> {
+    let mut req = reqwest::Client::new().get(url);
+    for (k, v) in headers.into_iter().flatten() {
+        req = req.header(k, v);
+    }
+    let resp = req
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(resp_error::network_error)?;
+    Ok(resp
+        .bytes()
+        .await
+        .map_err(resp_error::network_error)?
+        .to_vec())
+}
+
+/// http(s) url whose host is not localhost or a loopback, private or link-local
+/// ip literal, domains are not resolved
+pub fn is_public_url(url: &reqwest::Url) -> bool {
+    if !["http", "https"].contains(&url.scheme()) {
+        return false;
+    }
+    let Some(host) = url.host_str() else {
+        return false;
+    };
+    match host.trim_start_matches('[').trim_end_matches(']').parse() {
+        Ok(std::net::IpAddr::V4(ip)) => is_public_ipv4(ip),
+        Ok(std::net::IpAddr::V6(ip)) => match ip.to_ipv4_mapped() {
+            Some(v4) => is_public_ipv4(v4),
+            None => {
+                let first = ip.segments()[0];
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    || first & 0xfe00 == 0xfc00
+                    || first & 0xffc0 == 0xfe80)
+            }
+        },
+        Err(_) => {
+            let host = host.trim_end_matches('.').to_ascii_lowercase();
+            host != "localhost" && !host.ends_with(".localhost")
+        }
+    }
+}
+
+fn is_public_ipv4(ip: std::net::Ipv4Addr) -> bool {
+    !(ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast())
+}
+
+/// download at most `max_size` bytes, 0 for unlimited,
+/// only public urls are fetched, redirects included
+pub async fn download_capped(url: &str, max_size: u64) -> Result<Vec<u8>, RespError> {
+    let parsed = reqwest::Url::parse(url).map_err(resp_error::bad_param)?;
+    if !is_public_url(&parsed) {
+        return Err(resp_error::bad_param(format!("url {} not allowed", url)));
+    }
+    let client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::custom(|attempt| {
+            if attempt.previous().len() >= 10 {
+                attempt.error("too many redirects")
+            } else if !is_public_url(attempt.url()) {
+                attempt.error("redirect to non-public url")
+            } else {
+                attempt.follow()
+            }
+        }))
+        .build()
+        .map_err(resp_error::network_error)?;
+    let mut resp = client
+        .get(parsed)
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(resp_error::network_error)?;
+    let too_large = || {
+        file_error(
+            FILE_TOO_LARGE,
+            format!("{} exceeds limit {}", url, max_size),
+        )
+    };
+    if max_size > 0 && resp.content_length().unwrap_or_default() > max_size {
+        return Err(too_large());
+    }
+    let mut data = vec![];
+    while let Some(chunk) = resp.chunk().await.map_err(resp_error::network_error)? {
+        data.extend_from_slice(&chunk);
+        if max_size > 0 && data.len() as u64 > max_size {
+            return Err(too_large());
+        }
+    }
+    Ok(data)
+}
+
+/// a fragmented upload staged on disk
+struct Fragment {
+    name: String,
+    total_size: u64,
+    path: PathBuf,
+    /// received chunks, offset to length
+    chunks: BTreeMap<u64, u64>,
+    updated: Instant,
+}
+
+impl Fragment {
+    fn is_complete(&self) -> bool {
+        let mut end = 0;
+        for (offset, len) in &self.chunks {
+            if *offset > end {
+                return false;
+            }
+            end = end.max(offset + len);
+        }
+        end == self.total_size
+    }
+}
+
+/// staging area for `upload_file_fragmented`
+pub struct Fragments {
+    dir: PathBuf,
+    timeout: Duration,
+    fragments: Mutex<HashMap<String, Fragment>>,
+}
+
+impl Fragments {
+    pub fn new(dir: PathBuf, timeout: Duration) -> Self {
+        Self {
+            dir,
+            timeout,
+            fragments: Mutex::default(),
+        }
+    }
+
+    pub async fn prepare(&self, name: String, total_size: u64) -> Result<String, RespError> {
+        tokio::fs::create_dir_all(&self.dir)
+            .await
+            .map_err(resp_error::filesystem_error)?;
+        // unguessable, so other clients can not write into or finish this upload
+        let id = hex::encode(rand::random::<[u8; 16]>());
+        let path = self.dir.join(format!("{}.part", id));
+        let file = tokio::fs::File::create(&path)
+            .await
+            .map_err(resp_error::filesystem_error)?;
+        file.set_len(total_size)
+            .await
+            .map_err(resp_error::filesystem_error)?;
+        self.fragments.lock().await.insert(
+            id.clone(),
+            Fragment {
+                name,
+                total_size,
+                path,
+                chunks: BTreeMap::default(),
+                updated: Instant::now(),
+            },
+        );
+        Ok(id)
+    }
+
+    /// chunks may arrive in any order
+    pub async fn transfer(&self, id: &str, offset: u64, data: &[u8]) -> Result<(), RespError> {
+        let path = {
+            let fragments = self.fragments.lock().await;
+            let fragment = fragments
+                .get(id)
+                .ok_or_else(|| resp_error::bad_param(format!("unknown file_id:{}", id)))?;
+            if offset + data.len() as u64 > fragment.total_size {
+                return Err(resp_error::bad_param("chunk out of total_size"));
+            }
+            fragment.path.clone()
+        };
+        let mut file = tokio::fs::OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .await
+            .map_err(resp_error::filesystem_error)?;
+        file.seek(SeekFrom::Start(offset))
+            .await
+            .map_err(resp_error::filesystem_error)?;
+        file.write_all(data)
+            .await
+            .map_err(resp_error::filesystem_error)?;
+        file.flush().await.map_err(resp_error::filesystem_error)?;
+        if let Some(fragment) = self.fragments.lock().await.get_mut(id) {
+            fragment.chunks.insert(offset, data.len() as u64);
+            fragment.updated = Instant::now();
+        }
+        Ok(())
+    }
+
+    /// remove the staged upload and return its name and content
+    pub async fn finish(&self, id: &str) -> Result<(String, Vec<u8>), RespError> {
+        let fragment = {
+            let mut fragments = self.fragments.lock().await;
+            match fragments.get(id) {
+                Some(f) if !f.is_complete() => {
+                    return Err(resp_error::bad_param("file transfer incomplete"))
+                }
+                Some(_) => fragments.remove(id).unwrap(),
+                None => return Err(resp_error::bad_param(format!("unknown file_id:{}", id))),
+            }
+        };
+        let data = tokio::fs::read(&fragment.path)
+            .await
+            .map_err(resp_error::filesystem_error);
+        tokio::fs::remove_file(&fragment.path).await.ok();
+        Ok((fragment.name, data?))
+    }
+
+    /// discard uploads not updated within timeout
+    pub async fn clean(&self) {
+        let expired: Vec<Fragment> = {
+            let mut fragments = self.fragments.lock().await;
+            let ids: Vec<String> = fragments
+                .iter()
+                .filter(|(_, f)| f.updated.elapsed() > self.timeout)
+                .map(|(id, _)| id.clone())
+                .collect();
+            ids.iter().filter_map(|id| fragments.remove(id)).collect()
+        };
+        for fragment in expired {
+            tracing::info!(
+                target: crate::WALLE_K,
+                "discard abandoned fragmented upload {}",
+                fragment.name
+            );
+            tokio::fs::remove_file(&fragment.path).await.ok();
+        }
+    }
+}
+
+/// local copies of Kook assets, named by sha256 of the asset url
+pub struct FileCache {
+    dir: PathBuf,
+    /// total bytes kept, 0 for unlimited
+    max_size: u64,
+    /// zero for unlimited
+    max_age: Duration,
+    /// bytes of a single download, 0 for unlimited
+    max_file_size: u64,
+}
+
+impl FileCache {
+    pub fn new(dir: PathBuf, max_size: u64, max_age: Duration, max_file_size: u64) -> Self {
+        Self {
+            dir,
+            max_size,
+            max_age,
+            max_file_size,
+        }
+    }
+
+    /// remove cached files older than max_age, then the oldest ones until under max_size
+    pub async fn clean(&self) {
+        let Ok(mut dir) = tokio::fs::read_dir(&self.dir).await else {
+            return;
+        };
+        let mut files = vec![];
+        while let Ok(Some(entry)) = dir.next_entry().await {
+            let Ok(meta) = entry.metadata().await else {
+                continue;
+            };
+            if meta.is_file() {
+                let modified = meta.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                files.push((modified, meta.len(), entry.path()));
+            }
+        }
+        files.sort();
+        let mut total: u64 = files.iter().map(|(_, len, _)| len).sum();
+        let now = SystemTime::now();
+        for (modified, len, path) in files {
+            let expired = !self.max_age.is_zero()
+                && now.duration_since(modified).unwrap_or_default() > self.max_age;
+            let oversize = self.max_size > 0 && total > self.max_size;
+            // oldest first, nothing newer can be expired either
+            if !expired && !oversize {
+                break;
+            }
+            if tokio::fs::remove_file(&path).await.is_ok() {
+                total -= len;
+            }
+        }
+    }
+
+    /// path of the cached asset, download it first if not cached yet
+    pub async fn fetch(&self, url: &str) -> Result<PathBuf, RespError> {
+        let path = self.dir.join(sha256_hex(url.as_bytes()));
+        if tokio::fs::try_exists(&path).await.unwrap_or(false) {
+            return Ok(path);
+        }
+        let data = download_capped(url, self.max_file_size).await?;
+        tokio::fs::create_dir_all(&self.dir)
+            .await
+            .map_err(resp_error::filesystem_error)?;
+        // unique per fetch, concurrent fetches of the same url must not share it
+        let temp = path.with_extension(format!("{}.tmp", hex::encode(rand::random::<[u8; 8]>())));
+        tokio::fs::write(&temp, data)
+            .await
+            .map_err(resp_error::filesystem_error)?;
+        tokio::fs::rename(&temp, &path)
+            .await
+            .map_err(resp_error::filesystem_error)?;
+        Ok(path)
+    }
+}
+
+/// write through a temp file then rename, readers never see a partial file
+pub async fn write_atomic(path: &PathBuf, data: &[u8]) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+    let temp = path.with_extension(format!("{}.tmp", hex::encode(rand::random::<[u8; 8]>())));
+    if let Err(e) = tokio::fs::write(&temp, data).await {
+        tokio::fs::remove_file(&temp).await.ok();
+        return Err(e);
+    }
+    tokio::fs::rename(&temp, path).await
+}
+
+/// file name from the last segment of url
+pub fn url_file_name(url: &str) -> String {
+    url.split(['?', '#'])
+        .next()
+        .and_then(|u| u.rsplit('/').next())
+        .filter(|n| !n.is_empty())
+        .unwrap_or("-")
+        .to_owned()
+}
+
+pub async fn read_range(path: &PathBuf, offset: u64, size: u64) -> Result<Vec<u8>, RespError> {
+    let mut file = tokio::fs::File::open(path)
+        .await
+        .map_err(resp_error::filesystem_error)?;
+    file.seek(SeekFrom::Start(offset))
+        .await
+        .map_err(resp_error::filesystem_error)?;
+    let mut data = vec![];
+    file.take(size)
+        .read_to_end(&mut data)
+        .await
+        .map_err(resp_error::filesystem_error)?;
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fragment(total_size: u64, chunks: &[(u64, u64)]) -> Fragment {
+        Fragment {
+            name: "test".to_owned(),
+            total_size,
+            path: PathBuf::default(),
+            chunks: chunks.iter().copied().collect(),
+            updated: Instant::now(),
+        }
+    }
+
+    #[test]
+    fn fragment_complete() {
+        assert!(fragment(0, &[]).is_complete());
+        assert!(fragment(10, &[(0, 10)]).is_complete());
+        // out of order
+        assert!(fragment(10, &[(5, 5), (0, 5)]).is_complete());
+        // overlapping
+        assert!(fragment(10, &[(0, 6), (4, 6)]).is_complete());
+        assert!(fragment(10, &[(0, 10), (2, 3)]).is_complete());
+    }
+
+    #[test]
+    fn fragment_incomplete() {
+        assert!(!fragment(10, &[]).is_complete());
+        assert!(!fragment(10, &[(0, 4), (5, 5)]).is_complete());
+        assert!(!fragment(10, &[(1, 9)]).is_complete());
+        assert!(!fragment(10, &[(0, 9)]).is_complete());
+    }
+
+    #[test]
+    fn data_uri() {
+        assert_eq!(
+            decode_data_uri("data:image/png;base64,aGVsbG8=").unwrap(),
+            Some((Some("image/png".to_owned()), b"hello".to_vec()))
+        );
+        assert_eq!(
+            decode_data_uri("data:text/plain;charset=utf-8,hi").unwrap(),
+            Some((Some("text/plain".to_owned()), b"hi".to_vec()))
+        );
+        assert_eq!(
+            decode_data_uri("data:,hi").unwrap(),
+            Some((None, b"hi".to_vec()))
+        );
+        assert_eq!(
+            decode_data_uri("https://img.kookapp.cn/a.png").unwrap(),
+            None
+        );
+        assert!(decode_data_uri("data:image/png;base64").is_err());
+        assert!(decode_data_uri("data:image/png;base64,!!!").is_err());
+    }
+
+    #[test]
+    fn kook_asset() {
+        assert!(is_kook_asset("https://img.kookapp.cn/assets/a.png"));
+        assert!(is_kook_asset("https://img.kaiheila.cn/assets/a.png?x=1"));
+        assert!(is_kook_asset("http://kookapp.cn"));
+        assert!(!is_kook_asset("https://kookapp.cn.evil.com/a.png"));
+        assert!(!is_kook_asset("https://evilkookapp.cn/a.png"));
+        assert!(!is_kook_asset("https://img.kookapp.cn@evil.com/a.png"));
+        assert!(!is_kook_asset("https://img.kookapp.cn:8080/a.png"));
+        assert!(!is_kook_asset("file://img.kookapp.cn/etc/passwd"));
+        assert!(!is_kook_asset("img.kookapp.cn/a.png"));
+    }
+
+    #[test]
+    fn public_url() {
+        let public = |url: &str| is_public_url(&reqwest::Url::parse(url).unwrap());
+        assert!(public("https://example.com/a.png"));
+        assert!(public("http://8.8.8.8/a.png"));
+        assert!(!public("ftp://example.com/a.png"));
+        assert!(!public("http://localhost:8080/"));
+        assert!(!public("http://api.localhost/"));
+        assert!(!public("http://127.0.0.1/"));
+        assert!(!public("http://0x7f.1/"));
+        assert!(!public("http://10.0.0.1/"));
+        assert!(!public("http://192.168.1.1/"));
+        assert!(!public("http://169.254.169.254/latest/meta-data"));
+        assert!(!public("http://[::1]/"));
+        assert!(!public("http://[fd00::1]/"));
+        assert!(!public("http://[::ffff:127.0.0.1]/"));
+    }
+}
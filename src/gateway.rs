@@ -0,0 +1,330 @@
+use std::{
+    fmt::Display,
+    sync::{
+        atomic::{AtomicI64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use futures_util::{SinkExt, StreamExt};
+use kook::{
+    prelude::{Event as _KookEvent, EventExtra},
+    Kook,
+};
+use serde::Deserialize;
+use tokio::sync::{mpsc::UnboundedSender, watch};
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::dedup::Dedup;
+
+pub(crate) type KookEvent = _KookEvent<EventExtra>;
+
+/// interval between pings required by Kook
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+/// connection is considered dead when no pong arrives within this after a ping
+const HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(6);
+const HELLO_TIMEOUT: Duration = Duration::from_secs(6);
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+/// a session lasting longer than this resets the backoff
+const STABLE_SESSION: Duration = Duration::from_secs(60);
+
+/// exponential backoff between retries
+pub struct Backoff(Duration);
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self(MIN_BACKOFF)
+    }
+}
+
+impl Backoff {
+    /// delay to wait before the next retry
+    pub fn next(&mut self) -> Duration {
+        let delay = self.0;
+        self.0 = (self.0 * 2).min(MAX_BACKOFF);
+        delay
+    }
+
+    pub fn reset(&mut self) {
+        self.0 = MIN_BACKOFF;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GatewayState {
+    Connecting,
+    Online,
+    Resuming,
+    Offline,
+}
+
+impl GatewayState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Connecting => "connecting",
+            Self::Online => "online",
+            Self::Resuming => "resuming",
+            Self::Offline => "offline",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum GatewayError {
+    /// websocket closed or failed
+    Disconnected(String),
+    /// hello carried a non-zero code
+    Hello(i64),
+    /// server asked for a fresh session, or rejected resuming
+    Reconnect,
+}
+
+impl Display for GatewayError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Disconnected(e) => write!(f, "disconnected:{}", e),
+            Self::Hello(code) => write!(f, "hello failed with code {}", code),
+            Self::Reconnect => write!(f, "reconnect required"),
+        }
+    }
+}
+
+/// signalling frame of Kook websocket gateway
+#[derive(Debug, Deserialize)]
+struct Frame {
+    s: u8,
+    #[serde(default)]
+    d: serde_json::Value,
+    sn: Option<i64>,
+}
+
+fn now_millis() -> i64 {
+    chrono::Utc::now().timestamp_millis()
+}
+
+/// Kook websocket gateway client, forwards events to the handler channel
+/// and tracks connection state for `get_status`
+pub struct Gateway {
+    kook: Arc<Kook>,
+    tx: UnboundedSender<KookEvent>,
+    dedup: Dedup,
+    state: watch::Sender<GatewayState>,
+    /// unix millis, 0 for never
+    last_event: AtomicI64,
+    last_heartbeat_ack: AtomicI64,
+    /// sn of the last dispatched event, used for heartbeat and resume
+    sn: AtomicI64,
+    session_id: Mutex<Option<String>>,
+}
+
+impl Gateway {
+    pub fn new(kook: Arc<Kook>, tx: UnboundedSender<KookEvent>, dedup: Dedup) -> Self {
+        Self {
+            kook,
+            tx,
+            dedup,
+            state: watch::channel(GatewayState::Offline).0,
+            last_event: AtomicI64::default(),
+            last_heartbeat_ack: AtomicI64::default(),
+            sn: AtomicI64::default(),
+            session_id: Mutex::default(),
+        }
+    }
+
+    pub fn state(&self) -> GatewayState {
+        *self.state.borrow()
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<GatewayState> {
+        self.state.subscribe()
+    }
+
+    pub fn last_event_time(&self) -> Option<f64> {
+        Some(self.last_event.load(Ordering::Relaxed))
+            .filter(|t| *t > 0)
+            .map(|t| t as f64 / 1000.0)
+    }
+
+    /// duplicate events discarded since start
+    pub fn duplicate_events(&self) -> u64 {
+        self.dedup.discarded()
+    }
+
+    pub fn last_heartbeat_ack(&self) -> Option<f64> {
+        Some(self.last_heartbeat_ack.load(Ordering::Relaxed))
+            .filter(|t| *t > 0)
+            .map(|t| t as f64 / 1000.0)
+    }
+
+    pub fn set_state(&self, state: GatewayState) {
+        self.state.send_if_modified(|s| {
+            if *s != state {
+                tracing::info!(target: crate::WALLE_K, "Kook gateway {}", state.as_str());
+                *s = state;
+                true
+            } else {
+                false
+            }
+        });
+    }
+
+    fn session_id(&self) -> Option<String> {
+        self.session_id.lock().unwrap().clone()
+    }
+
+    fn reset_session(&self) {
+        *self.session_id.lock().unwrap() = None;
+        self.sn.store(0, Ordering::Relaxed);
+    }
+
+    /// keep the gateway connected, reconnecting with backoff and resuming
+    /// the last session when possible
+    pub async fn run(&self) {
+        let mut backoff = Backoff::default();
+        loop {
+            let resume = self.session_id().is_some();
+            self.set_state(if resume {
+                GatewayState::Resuming
+            } else {
+                GatewayState::Connecting
+            });
+            let started = Instant::now();
+            let r = self.session(resume).await;
+            self.set_state(GatewayState::Offline);
+            match r {
+                Err(GatewayError::Reconnect) => {
+                    tracing::warn!(target: crate::WALLE_K, "Kook gateway session expired, starting a new one");
+                    self.reset_session();
+                }
+                Err(e) => tracing::warn!(target: crate::WALLE_K, "Kook gateway {}", e),
+                Ok(()) => {}
+            }
+            if started.elapsed() > STABLE_SESSION {
+                backoff.reset();
+            }
+            let delay = backoff.next();
+            tracing::info!(target: crate::WALLE_K, "Kook gateway reconnecting in {:?}", delay);
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    async fn session(&self, resume: bool) -> Result<(), GatewayError> {
+        let mut url = self
+            .kook
+            .get_gateway(false)
+            .await
+            .map_err(|e| GatewayError::Disconnected(e.to_string()))?
+            .url;
+        if resume {
+            url = format!(
+                "{}&resume=1&sn={}&session_id={}",
+                url,
+                self.sn.load(Ordering::Relaxed),
+                self.session_id().unwrap_or_default()
+            );
+        }
+        let (ws, _) = tokio_tungstenite::connect_async(&url)
+            .await
+            .map_err(|e| GatewayError::Disconnected(e.to_string()))?;
+        let (mut sink, mut stream) = ws.split();
+        let hello_deadline = tokio::time::Instant::now() + HELLO_TIMEOUT;
+        let mut hello = false;
+        let mut heartbeat = tokio::time::interval(HEARTBEAT_INTERVAL);
+        heartbeat.tick().await;
+        let mut pong_deadline = None;
+        loop {
+            tokio::select! {
+                msg = stream.next() => {
+                    let text = match msg {
+                        Some(Ok(Message::Text(text))) => text,
+                        Some(Ok(Message::Close(frame))) => {
+                            return Err(GatewayError::Disconnected(format!("closed:{:?}", frame)))
+                        }
+                        Some(Ok(_)) => continue,
+                        Some(Err(e)) => return Err(GatewayError::Disconnected(e.to_string())),
+                        None => return Err(GatewayError::Disconnected("stream ended".to_owned())),
+                    };
+                    let frame: Frame = match serde_json::from_str(&text) {
+                        Ok(frame) => frame,
+                        Err(e) => {
+                            tracing::warn!(target: crate::WALLE_K, "bad gateway frame:{}", e);
+                            continue;
+                        }
+                    };
+                    match frame.s {
+                        0 => {
+                            if let Some(sn) = frame.sn {
+                                self.sn.store(sn, Ordering::Relaxed);
+                            }
+                            self.dispatch(frame.sn, frame.d);
+                        }
+                        1 => {
+                            let code = frame
+                                .d
+                                .get("code")
+                                .and_then(|c| c.as_i64())
+                                .unwrap_or_default();
+                            match code {
+                                0 => {}
+                                // missing params, expired session or invalid sn
+                                40106..=40108 => return Err(GatewayError::Reconnect),
+                                code => return Err(GatewayError::Hello(code)),
+                            }
+                            hello = true;
+                            if let Some(session_id) = frame.d.get("session_id").and_then(|s| s.as_str()) {
+                                *self.session_id.lock().unwrap() = Some(session_id.to_owned());
+                            }
+                            // a resumed session goes online once replay is acknowledged
+                            if !resume {
+                                self.set_state(GatewayState::Online);
+                            }
+                        }
+                        3 => {
+                            pong_deadline = None;
+                            self.last_heartbeat_ack.store(now_millis(), Ordering::Relaxed);
+                        }
+                        5 => return Err(GatewayError::Reconnect),
+                        6 => self.set_state(GatewayState::Online),
+                        _ => {}
+                    }
+                }
+                _ = heartbeat.tick() => {
+                    sink.send(Message::Text(serde_json::json!({ "s": 2, "sn": self.sn.load(Ordering::Relaxed) }).to_string()))
+                        .await
+                        .map_err(|e| GatewayError::Disconnected(e.to_string()))?;
+                    pong_deadline.get_or_insert(tokio::time::Instant::now() + HEARTBEAT_TIMEOUT);
+                }
+                _ = tokio::time::sleep_until(pong_deadline.unwrap_or(hello_deadline)), if pong_deadline.is_some() => {
+                    return Err(GatewayError::Disconnected("heartbeat timeout".to_owned()));
+                }
+                _ = tokio::time::sleep_until(hello_deadline), if !hello => {
+                    return Err(GatewayError::Disconnected("hello timeout".to_owned()));
+                }
+            }
+        }
+    }
+
+    /// forward one event payload to the handler channel, dropping duplicates
+    /// keyed on msg_id, or sn when msg_id is missing
+    pub fn dispatch(&self, sn: Option<i64>, data: serde_json::Value) {
+        self.last_event.store(now_millis(), Ordering::Relaxed);
+        match serde_json::from_value::<KookEvent>(data) {
+            Ok(event) => {
+                let key = match (event.msg_id.is_empty(), sn) {
+                    (false, _) => event.msg_id.clone(),
+                    (true, Some(sn)) => format!("sn:{}", sn),
+                    (true, None) => {
+                        self.tx.send(event).ok();
+                        return;
+                    }
+                };
+                if self.dedup.check(key) {
+                    self.tx.send(event).ok();
+                }
+            }
+            Err(e) => tracing::debug!(target: crate::WALLE_K, "unparsed Kook event:{}", e),
+        }
+    }
+}
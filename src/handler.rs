@@ -1,18 +1,41 @@
-use std::{convert::TryFrom, sync::Arc};
+use std::{convert::TryFrom, sync::Arc, time::Duration};
 
-use kook::{prelude::Config as KookConfig, Kook};
+use kook::{Kook, KookError};
 use once_cell::sync::OnceCell;
 use tokio::task::JoinHandle;
 use walle_core::{
-    action::{Action, SendMessage},
+    action::{Action, GetFile, GetGuildMemberInfo, SendMessage, UploadFile},
     alt::ColoredAlt,
+    event::Event,
     prelude::{async_trait, WalleError, WalleResult},
     resp::{resp_error, Resp, RespError},
     structs::{Selft, SendMessageResp, Version},
-    ActionHandler, EventHandler, GetSelfs, GetStatus, GetVersion, OneBot,
+    util::{OneBotBytes, ValueMap},
+    value_map, ActionHandler, EventHandler, GetSelfs, GetStatus, GetVersion, OneBot,
 };
 
-use crate::parse::{event_parse, segments_to_str, KookAction};
+use crate::archive::Archiver;
+use crate::dedup::Dedup;
+use crate::events::EventBuffer;
+use crate::file::{
+    detect_mime, download, is_kook_asset, read_local, read_range, sha256_hex, url_file_name,
+    verify_sha256, FileCache, FileStore, Fragments,
+};
+use crate::gateway::{Backoff, Gateway, GatewayState};
+use crate::parse::{
+    decode_permissions, encode_permissions, event_parse, history_message_to_value, load_bytes,
+    segments_to_str, upload_cached, AddBlacklist, ChannelId, ChannelMember,
+    ChannelPermissionTarget, ChatCode, ClearActivity, CreateGame, CreateGuildEmoji, CreateInvite,
+    DeleteInvite, EmojiId, GetFileFragmented, GetGameList, GetMessage, GetMessageList, GuildId,
+    GuildMember, InviteTarget, KookAction, LatestEvents, MuteGuildMember, SetActivity,
+    SetChannelPermission, SetGuildMemberNickname, UpdateGuildEmoji, UpdateIntimacy,
+    UploadFileFragmented, UserId,
+};
+use crate::webhook::Webhook;
+use crate::{
+    transcode::{check_len, check_size},
+    FileConfig, HandlerConfig,
+};
 
 pub type RespReault = Result<Resp, RespError>;
 
@@ -23,10 +46,32 @@ pub fn to_resp(r: RespReault) -> WalleResult<Resp> {
     })
 }
 
+/// platform error retcode for actions rejected by Kook for lacking permission
+pub const PERMISSION_DENIED: u32 = 34001;
+
+/// Kook api codes of requests rejected for lacking permission
+const KOOK_FORBIDDEN: [i32; 2] = [403, 40300];
+
+fn kook_error(e: KookError) -> RespError {
+    match e {
+        KookError::HttpRespError(code, message) if KOOK_FORBIDDEN.contains(&code) => RespError {
+            retcode: PERMISSION_DENIED,
+            message,
+        },
+        e => resp_error::bad_handler(e.to_string()),
+    }
+}
+
 #[derive(Default)]
 pub struct KHandler {
     _self_id: OnceCell<String>,
     _kook: OnceCell<Arc<Kook>>,
+    _fragments: OnceCell<Arc<Fragments>>,
+    _cache: OnceCell<Arc<FileCache>>,
+    _file_config: OnceCell<FileConfig>,
+    _events: OnceCell<Arc<EventBuffer>>,
+    _gateway: OnceCell<Arc<Gateway>>,
+    files: FileStore,
 }
 
 impl KHandler {
@@ -38,6 +83,60 @@ impl KHandler {
             .get()
             .ok_or(resp_error::bad_handler("Kook not inited"))
     }
+    fn fragments(&self) -> Result<&Arc<Fragments>, RespError> {
+        self._fragments
+            .get()
+            .ok_or(resp_error::bad_handler("Kook not inited"))
+    }
+    fn cache(&self) -> Result<&Arc<FileCache>, RespError> {
+        self._cache
+            .get()
+            .ok_or(resp_error::bad_handler("Kook not inited"))
+    }
+    fn file_config(&self) -> Result<&FileConfig, RespError> {
+        self._file_config
+            .get()
+            .ok_or(resp_error::bad_handler("Kook not inited"))
+    }
+    fn gateway(&self) -> Result<&Arc<Gateway>, RespError> {
+        self._gateway
+            .get()
+            .ok_or(resp_error::bad_handler("Kook not inited"))
+    }
+    fn online(&self) -> bool {
+        self.gateway()
+            .map(|g| g.state() == GatewayState::Online)
+            .unwrap_or_default()
+    }
+    fn status_value(&self) -> ValueMap {
+        let (state, last_event_time, last_heartbeat_ack, duplicate_events) = match self.gateway() {
+            Ok(g) => (
+                g.state(),
+                g.last_event_time(),
+                g.last_heartbeat_ack(),
+                g.duplicate_events(),
+            ),
+            Err(_) => (GatewayState::Offline, None, None, 0),
+        };
+        let online = state == GatewayState::Online;
+        let (asset_cache_hits, asset_cache_misses) = self.files.stats();
+        value_map! {
+            "good": self.kook().is_ok() && online,
+            "bots": [{
+                "self": Selft {
+                    user_id: self.self_id(),
+                    platform: kook::KOOK.to_owned(),
+                },
+                "online": online,
+                "kook.state": state.as_str(),
+                "kook.last_event_time": last_event_time,
+                "kook.last_heartbeat_ack": last_heartbeat_ack,
+                "kook.duplicate_events": duplicate_events as i64,
+                "kook.asset_cache_hits": asset_cache_hits as i64,
+                "kook.asset_cache_misses": asset_cache_misses as i64
+            }]
+        }
+    }
 }
 
 #[async_trait]
@@ -56,7 +155,7 @@ impl GetSelfs for KHandler {
 #[async_trait]
 impl GetStatus for KHandler {
     async fn is_good(&self) -> bool {
-        self.kook().is_ok()
+        self.kook().is_ok() && self.online()
     }
 }
 
@@ -72,7 +171,7 @@ impl GetVersion for KHandler {
 
 #[async_trait]
 impl ActionHandler for KHandler {
-    type Config = KookConfig;
+    type Config = HandlerConfig;
     async fn start<AH, EH>(
         &self,
         ob: &Arc<OneBot<AH, EH>>,
@@ -83,31 +182,120 @@ impl ActionHandler for KHandler {
         EH: EventHandler + Send + Sync + 'static,
     {
         let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
-        let kook = Arc::new(Kook::new_from_config(config, tx));
-        self._self_id
-            .set(
-                kook.get_me()
-                    .await
-                    .map_err(|e| WalleError::Other(e.to_string()))?
-                    .id,
-            )
-            .ok();
+        let kook = Arc::new(Kook::new_from_config(config.kook, tx.clone()));
+        let mut backoff = Backoff::default();
+        let me = loop {
+            match kook.get_me().await {
+                Ok(me) => break me,
+                // Kook answered, retrying won't help with a bad token
+                Err(e @ KookError::HttpRespError(..)) => {
+                    return Err(WalleError::Other(e.to_string()))
+                }
+                Err(e) => {
+                    let delay = backoff.next();
+                    tracing::warn!(target: crate::WALLE_K, "get_me failed:{}, retry in {:?}", e, delay);
+                    tokio::time::sleep(delay).await;
+                }
+            }
+        };
+        self._self_id.set(me.id).ok();
         self._kook.set(kook.clone()).ok();
         let ob = ob.clone();
         let mut tasks = vec![];
         let id = self.self_id();
+        let events = Arc::new(EventBuffer::new(config.event.buffer_size));
+        self._events.set(events.clone()).ok();
+        let archiver = if config.archive.enable {
+            Some(Archiver::new(config.archive).await)
+        } else {
+            None
+        };
+        let dedup = Dedup::new(
+            Duration::from_secs(config.event.dedup_window),
+            config.event.dedup_size,
+        );
+        let gateway = Arc::new(Gateway::new(kook.clone(), tx, dedup));
+        self._gateway.set(gateway.clone()).ok();
+        let mut state_rx = gateway.subscribe();
+        let status_ob = ob.clone();
+        let status_events = events.clone();
+        let selft = Selft {
+            user_id: id.clone(),
+            platform: kook::KOOK.to_owned(),
+        };
+        tasks.push(tokio::spawn(async move {
+            while state_rx.changed().await.is_ok() {
+                let online = *state_rx.borrow() == GatewayState::Online;
+                let now = chrono::Utc::now();
+                let event = Event {
+                    id: format!(
+                        "status_update-{}",
+                        now.timestamp_nanos_opt().unwrap_or_default()
+                    ),
+                    time: now.timestamp_millis() as f64 / 1000.0,
+                    ty: "meta".to_owned(),
+                    detail_type: "status_update".to_owned(),
+                    sub_type: "".to_owned(),
+                    extra: value_map! {
+                        "status": {
+                            "good": online,
+                            "bots": [{
+                                "self": selft.clone(),
+                                "online": online
+                            }]
+                        }
+                    },
+                };
+                status_events.push(event.clone()).await;
+                status_ob.handle_event(event).await.ok();
+            }
+        }));
         tasks.push(tokio::spawn(async move {
             while let Some(event) = rx.recv().await {
-                if let Some(event) = event_parse(event, id.clone()).await {
+                if let Some(mut event) = event_parse(event, id.clone()).await {
+                    if let Some(ref archiver) = archiver {
+                        archiver.archive_event(&mut event).await;
+                    }
                     tracing::info!(target: crate::WALLE_K, "{}", event.colored_alt());
+                    events.push(event.clone()).await;
                     ob.handle_event(event) //todo
                         .await
                         .ok();
                 }
             }
         }));
+        if config.webhook.enable {
+            let webhook = Arc::new(Webhook::new(config.webhook, gateway));
+            let builder = webhook.bind().map_err(WalleError::Other)?;
+            tasks.push(tokio::spawn(webhook.serve(builder)));
+        } else {
+            tasks.push(tokio::spawn(async move { gateway.run().await }));
+        }
+        self.files
+            .load(config.file.asset_cache_file(), config.file.asset_cache_size)
+            .await;
+        let cache = Arc::new(FileCache::new(
+            config.file.cache_dir.join("files"),
+            config.file.cache_max_size,
+            Duration::from_secs(config.file.cache_max_age),
+            config.file.max_upload_size,
+        ));
+        self._cache.set(cache.clone()).ok();
+        self._file_config.set(config.file.clone()).ok();
+        let fragment_dir = config.file.cache_dir.join("fragments");
+        tokio::fs::remove_dir_all(&fragment_dir).await.ok();
+        let fragments = Arc::new(Fragments::new(
+            fragment_dir,
+            Duration::from_secs(config.file.fragment_timeout),
+        ));
+        self._fragments.set(fragments.clone()).ok();
         tasks.push(tokio::spawn(async move {
-            kook.start_ws().await.ok();
+            let mut interval = tokio::time::interval(Duration::from_secs(60));
+            loop {
+                interval.tick().await;
+                fragments.clean().await;
+                cache.clean().await;
+            }
         }));
         Ok(tasks)
     }
@@ -119,14 +307,50 @@ impl ActionHandler for KHandler {
         let a = action.action.clone();
         match KookAction::try_from(action) {
             Ok(action) => match action {
-                KookAction::GetLatestEvents(_) => Ok(Vec::<()>::new().into()), //todo
-                KookAction::GetStatus => Ok(self.get_status().await.into()),
+                KookAction::GetLatestEvents(c) => to_resp(self.get_latest_events(c).await),
+                KookAction::GetStatus => Ok(self.status_value().into()),
                 KookAction::GetSupportedActions => Ok(vec![
                     "get_latest_events",
                     "get_supported_actions",
                     "get_status",
                     "get_version",
                     "send_message",
+                    "get_friend_list",
+                    "upload_file",
+                    "upload_file_fragmented",
+                    "get_file",
+                    "get_file_fragmented",
+                    "kook.get_message",
+                    "kook.get_message_list",
+                    "get_guild_member_info",
+                    "kook.get_guild_emoji_list",
+                    "kook.create_guild_emoji",
+                    "kook.update_guild_emoji",
+                    "kook.delete_guild_emoji",
+                    "kook.kick_guild_member",
+                    "kook.get_blacklist",
+                    "kook.add_blacklist",
+                    "kook.remove_blacklist",
+                    "kook.mute_guild_member",
+                    "kook.unmute_guild_member",
+                    "kook.get_muted_members",
+                    "kook.set_guild_member_nickname",
+                    "kook.get_channel_permissions",
+                    "kook.set_channel_permission",
+                    "kook.delete_channel_permission",
+                    "kook.move_voice_users",
+                    "kook.kick_voice_user",
+                    "kook.set_activity",
+                    "kook.clear_activity",
+                    "kook.get_game_list",
+                    "kook.create_game",
+                    "kook.get_intimacy",
+                    "kook.update_intimacy",
+                    "kook.create_invite",
+                    "kook.get_invite_list",
+                    "kook.delete_invite",
+                    "kook.create_user_chat",
+                    "kook.delete_user_chat",
                 ]
                 .into()),
                 KookAction::GetVersion => Ok(Version {
@@ -137,6 +361,52 @@ impl ActionHandler for KHandler {
                 .into()),
 
                 KookAction::SendMessage(c) => to_resp(self.send_message(c).await),
+                KookAction::GetMessage(c) => to_resp(self.get_message(c).await),
+                KookAction::GetMessageList(c) => to_resp(self.get_message_list(c).await),
+                KookAction::UploadFile(c) => to_resp(self.upload_file(c).await),
+                KookAction::UploadFileFragmented(c) => {
+                    to_resp(self.upload_file_fragmented(c).await)
+                }
+                KookAction::GetFile(c) => to_resp(self.get_file(c).await),
+                KookAction::GetFileFragmented(c) => to_resp(self.get_file_fragmented(c).await),
+                KookAction::GetFriendList => to_resp(self.get_friend_list().await),
+                KookAction::CreateUserChat(c) => to_resp(self.create_user_chat(c).await),
+                KookAction::DeleteUserChat(c) => to_resp(self.delete_user_chat(c).await),
+                KookAction::GetGuildMemberInfo(c) => to_resp(self.get_guild_member_info(c).await),
+                KookAction::GetGuildEmojiList(c) => to_resp(self.get_guild_emoji_list(c).await),
+                KookAction::CreateGuildEmoji(c) => to_resp(self.create_guild_emoji(c).await),
+                KookAction::UpdateGuildEmoji(c) => to_resp(self.update_guild_emoji(c).await),
+                KookAction::DeleteGuildEmoji(c) => to_resp(self.delete_guild_emoji(c).await),
+                KookAction::KickGuildMember(c) => to_resp(self.kick_guild_member(c).await),
+                KookAction::GetBlacklist(c) => to_resp(self.get_blacklist(c).await),
+                KookAction::AddBlacklist(c) => to_resp(self.add_blacklist(c).await),
+                KookAction::RemoveBlacklist(c) => to_resp(self.remove_blacklist(c).await),
+                KookAction::MuteGuildMember(c) => to_resp(self.mute_guild_member(c, true).await),
+                KookAction::UnmuteGuildMember(c) => to_resp(self.mute_guild_member(c, false).await),
+                KookAction::GetMutedMembers(c) => to_resp(self.get_muted_members(c).await),
+                KookAction::SetGuildMemberNickname(c) => {
+                    to_resp(self.set_guild_member_nickname(c).await)
+                }
+                KookAction::GetChannelPermissions(c) => {
+                    to_resp(self.get_channel_permissions(c).await)
+                }
+                KookAction::SetChannelPermission(c) => {
+                    to_resp(self.set_channel_permission(c).await)
+                }
+                KookAction::DeleteChannelPermission(c) => {
+                    to_resp(self.delete_channel_permission(c).await)
+                }
+                KookAction::MoveVoiceUsers(c) => to_resp(self.move_voice_users(c).await),
+                KookAction::KickVoiceUser(c) => to_resp(self.kick_voice_user(c).await),
+                KookAction::SetActivity(c) => to_resp(self.set_activity(c).await),
+                KookAction::ClearActivity(c) => to_resp(self.clear_activity(c).await),
+                KookAction::GetGameList(c) => to_resp(self.get_game_list(c).await),
+                KookAction::CreateGame(c) => to_resp(self.create_game(c).await),
+                KookAction::GetIntimacy(c) => to_resp(self.get_intimacy(c).await),
+                KookAction::UpdateIntimacy(c) => to_resp(self.update_intimacy(c).await),
+                KookAction::CreateInvite(c) => to_resp(self.create_invite(c).await),
+                KookAction::GetInviteList(c) => to_resp(self.get_invite_list(c).await),
+                KookAction::DeleteInvite(c) => to_resp(self.delete_invite(c).await),
             },
             Err(_) => Ok(resp_error::unsupported_action(a).into()),
         }
@@ -148,7 +418,14 @@ impl KHandler {
         match content.detail_type.as_str() {
             "channel" => {
                 if let Some(ref channel_id) = content.channel_id {
-                    match segments_to_str(self.kook()?, content.message).await {
+                    match segments_to_str(
+                        self.kook()?,
+                        &self.files,
+                        self.file_config()?,
+                        content.message,
+                    )
+                    .await
+                    {
                         Ok((s, ty)) => {
                             let r = self
                                 .kook()?
@@ -176,7 +453,14 @@ impl KHandler {
             }
             "private" => {
                 if let Some(ref user_id) = content.user_id {
-                    match segments_to_str(self.kook()?, content.message).await {
+                    match segments_to_str(
+                        self.kook()?,
+                        &self.files,
+                        self.file_config()?,
+                        content.message,
+                    )
+                    .await
+                    {
                         Ok((s, ty)) => {
                             let r = self
                                 .kook()?
@@ -206,3 +490,801 @@ impl KHandler {
         }
     }
 }
+
+impl KHandler {
+    /// chat_code of the existing direct session with given user
+    async fn chat_code(
+        &self,
+        user_id: Option<String>,
+        chat_code: Option<String>,
+    ) -> Result<String, RespError> {
+        match (chat_code, user_id) {
+            (Some(code), _) => Ok(code),
+            (None, Some(user_id)) => self
+                .kook()?
+                .get_user_chat_list()
+                .await
+                .map_err(kook_error)?
+                .into_iter()
+                .find(|chat| chat.target_info.id == user_id)
+                .map(|chat| chat.code)
+                .ok_or_else(|| {
+                    resp_error::bad_param(format!("no direct chat with user:{}", user_id))
+                }),
+            (None, None) => Err(resp_error::bad_param("user_id or chat_code required")),
+        }
+    }
+
+    pub async fn get_message(&self, c: GetMessage) -> RespReault {
+        let kook = self.kook()?;
+        let message = match c.detail_type.as_deref() {
+            None | Some("channel") => kook.view_message(&c.message_id).await,
+            Some("private") => {
+                let code = self.chat_code(c.user_id, c.chat_code).await?;
+                kook.view_direct_message(&code, &c.message_id).await
+            }
+            Some(ty) => return Err(resp_error::unsupported_param(format!("detail_type:{}", ty))),
+        }
+        .map_err(kook_error)?;
+        Ok(history_message_to_value(message).into())
+    }
+
+    pub async fn get_message_list(&self, c: GetMessageList) -> RespReault {
+        let (cursor, flag) = match (c.before, c.after) {
+            (Some(_), Some(_)) => {
+                return Err(resp_error::bad_param("before and after are exclusive"))
+            }
+            (Some(id), None) => (Some(id), Some("before")),
+            (None, Some(id)) => (Some(id), Some("after")),
+            (None, None) => (None, None),
+        };
+        let limit = c.limit.unwrap_or(50);
+        if limit == 0 || limit > 100 {
+            return Err(resp_error::bad_param("limit must be within 1-100"));
+        }
+        let kook = self.kook()?;
+        let messages = match c.detail_type.as_str() {
+            "channel" => {
+                let Some(channel_id) = c.channel_id else {
+                    return Err(resp_error::bad_param("channel_id required"));
+                };
+                kook.get_message_list(&channel_id, cursor.as_deref(), None, flag, Some(limit))
+                    .await
+            }
+            "private" => {
+                if c.chat_code.is_none() && c.user_id.is_none() {
+                    return Err(resp_error::bad_param("user_id or chat_code required"));
+                }
+                kook.get_direct_message_list(
+                    c.chat_code.as_deref(),
+                    c.user_id.as_deref(),
+                    cursor.as_deref(),
+                    flag,
+                    Some(limit),
+                )
+                .await
+            }
+            ty => return Err(resp_error::unsupported_param(format!("detail_type:{}", ty))),
+        }
+        .map_err(kook_error)?;
+        Ok(messages
+            .into_iter()
+            .map(history_message_to_value)
+            .collect::<Vec<_>>()
+            .into())
+    }
+}
+
+fn user_chat_to_friend(chat: kook::prelude::UserChat) -> ValueMap {
+    value_map! {
+        "user_id": chat.target_info.id,
+        "user_name": chat.target_info.username,
+        "user_displayname": "",
+        "user_remark": "",
+        "kook.chat_code": chat.code,
+        "kook.latest_msg_time": chat.latest_msg_time as f64 / 1000.0
+    }
+}
+
+impl KHandler {
+    pub async fn get_friend_list(&self) -> RespReault {
+        let chats = self
+            .kook()?
+            .get_user_chat_list()
+            .await
+            .map_err(kook_error)?;
+        Ok(chats
+            .into_iter()
+            .map(user_chat_to_friend)
+            .collect::<Vec<_>>()
+            .into())
+    }
+
+    pub async fn create_user_chat(&self, c: UserId) -> RespReault {
+        let chat = self
+            .kook()?
+            .create_user_chat(&c.user_id)
+            .await
+            .map_err(kook_error)?;
+        Ok(user_chat_to_friend(chat).into())
+    }
+
+    pub async fn delete_user_chat(&self, c: ChatCode) -> RespReault {
+        self.kook()?
+            .delete_user_chat(&c.chat_code)
+            .await
+            .map_err(kook_error)?;
+        Ok(().into())
+    }
+}
+
+impl KHandler {
+    pub async fn get_guild_emoji_list(&self, c: GuildId) -> RespReault {
+        let emojis = self
+            .kook()?
+            .get_guild_emoji_list(&c.guild_id)
+            .await
+            .map_err(kook_error)?;
+        Ok(emojis
+            .into_iter()
+            .map(|e| {
+                value_map! {
+                    "emoji_id": e.id,
+                    "name": e.name,
+                    "user_id": e.user_info.id
+                }
+            })
+            .collect::<Vec<_>>()
+            .into())
+    }
+
+    pub async fn create_guild_emoji(&self, c: CreateGuildEmoji) -> RespReault {
+        let data = load_bytes(self.file_config()?, c.url, c.path, c.bytes).await?;
+        let emoji = self
+            .kook()?
+            .create_guild_emoji(c.name.as_deref(), &c.guild_id, data)
+            .await
+            .map_err(kook_error)?;
+        Ok(value_map! {
+            "emoji_id": emoji.id,
+            "name": emoji.name
+        }
+        .into())
+    }
+
+    pub async fn update_guild_emoji(&self, c: UpdateGuildEmoji) -> RespReault {
+        self.kook()?
+            .update_guild_emoji(&c.name, &c.emoji_id)
+            .await
+            .map_err(kook_error)?;
+        Ok(().into())
+    }
+
+    pub async fn delete_guild_emoji(&self, c: EmojiId) -> RespReault {
+        self.kook()?
+            .delete_guild_emoji(&c.emoji_id)
+            .await
+            .map_err(kook_error)?;
+        Ok(().into())
+    }
+}
+
+impl KHandler {
+    pub async fn kick_guild_member(&self, c: GuildMember) -> RespReault {
+        self.kook()?
+            .kickout_guild_member(&c.guild_id, &c.user_id)
+            .await
+            .map_err(kook_error)?;
+        Ok(().into())
+    }
+
+    pub async fn get_blacklist(&self, c: GuildId) -> RespReault {
+        let list = self
+            .kook()?
+            .get_blacklist(&c.guild_id)
+            .await
+            .map_err(kook_error)?;
+        Ok(list
+            .into_iter()
+            .map(|b| {
+                value_map! {
+                    "user_id": b.user_id,
+                    "user_name": b.user.username,
+                    "reason": b.remark,
+                    "time": b.created_time as f64 / 1000.0
+                }
+            })
+            .collect::<Vec<_>>()
+            .into())
+    }
+
+    pub async fn add_blacklist(&self, c: AddBlacklist) -> RespReault {
+        if matches!(c.delete_message_days, Some(d) if d > 7) {
+            return Err(resp_error::bad_param(
+                "delete_message_days must be within 0-7",
+            ));
+        }
+        self.kook()?
+            .create_blacklist(
+                &c.guild_id,
+                &c.user_id,
+                c.reason.as_deref(),
+                c.delete_message_days,
+            )
+            .await
+            .map_err(kook_error)?;
+        Ok(().into())
+    }
+
+    pub async fn remove_blacklist(&self, c: GuildMember) -> RespReault {
+        self.kook()?
+            .delete_blacklist(&c.guild_id, &c.user_id)
+            .await
+            .map_err(kook_error)?;
+        Ok(().into())
+    }
+}
+
+/// Kook guild mute type, 1 for mic and 2 for headset
+fn mute_type(ty: Option<&str>) -> Result<u8, RespError> {
+    match ty {
+        None | Some("mic") => Ok(1),
+        Some("headset") => Ok(2),
+        Some(ty) => Err(resp_error::unsupported_param(format!("type:{}", ty))),
+    }
+}
+
+impl KHandler {
+    pub async fn get_guild_member_info(&self, c: GetGuildMemberInfo) -> RespReault {
+        let kook = self.kook()?;
+        let user = kook
+            .get_user_view(&c.user_id, Some(&c.guild_id))
+            .await
+            .map_err(kook_error)?;
+        // the mute list needs extra permission, leave `kook.muted` null without it
+        let muted = match kook.get_guild_mute_list(&c.guild_id).await {
+            Ok(mutes) => {
+                let mut muted = vec![];
+                if mutes.mic.user_ids.contains(&c.user_id) {
+                    muted.push("mic");
+                }
+                if mutes.headset.user_ids.contains(&c.user_id) {
+                    muted.push("headset");
+                }
+                Some(muted)
+            }
+            Err(e) => match kook_error(e) {
+                e if e.retcode == PERMISSION_DENIED => {
+                    tracing::debug!(target: crate::WALLE_K, "no permission to get guild mute list");
+                    None
+                }
+                e => return Err(e),
+            },
+        };
+        Ok(value_map! {
+            "user_id": user.id,
+            "user_name": user.username,
+            "user_displayname": user.nickname,
+            "kook.muted": muted
+        }
+        .into())
+    }
+
+    pub async fn mute_guild_member(&self, c: MuteGuildMember, mute: bool) -> RespReault {
+        let ty = mute_type(c.ty.as_deref())?;
+        let kook = self.kook()?;
+        if mute {
+            kook.create_guild_mute(&c.guild_id, &c.user_id, ty).await
+        } else {
+            kook.delete_guild_mute(&c.guild_id, &c.user_id, ty).await
+        }
+        .map_err(kook_error)?;
+        Ok(().into())
+    }
+
+    pub async fn get_muted_members(&self, c: GuildId) -> RespReault {
+        let mutes = self
+            .kook()?
+            .get_guild_mute_list(&c.guild_id)
+            .await
+            .map_err(kook_error)?;
+        Ok(value_map! {
+            "mic": mutes.mic.user_ids,
+            "headset": mutes.headset.user_ids
+        }
+        .into())
+    }
+}
+
+impl KHandler {
+    pub async fn set_guild_member_nickname(&self, c: SetGuildMemberNickname) -> RespReault {
+        let user_id = c.user_id.filter(|id| *id != self.self_id());
+        let nickname = c.nickname.filter(|n| !n.is_empty());
+        if matches!(nickname, Some(ref n) if n.chars().count() < 2 || n.chars().count() > 64) {
+            return Err(resp_error::bad_param("nickname length must be within 2-64"));
+        }
+        self.kook()?
+            .update_guild_nickname(&c.guild_id, nickname.as_deref(), user_id.as_deref())
+            .await
+            .map_err(kook_error)?;
+        Ok(().into())
+    }
+}
+
+/// Kook channel-role target type and value
+fn permission_target(
+    role_id: Option<String>,
+    user_id: Option<String>,
+) -> Result<(&'static str, String), RespError> {
+    match (role_id, user_id) {
+        (Some(role_id), None) => Ok(("role_id", role_id)),
+        (None, Some(user_id)) => Ok(("user_id", user_id)),
+        _ => Err(resp_error::bad_param("one of role_id or user_id required")),
+    }
+}
+
+impl KHandler {
+    pub async fn get_channel_permissions(&self, c: ChannelId) -> RespReault {
+        let perms = self
+            .kook()?
+            .get_channel_role_index(&c.channel_id)
+            .await
+            .map_err(kook_error)?;
+        Ok(value_map! {
+            "roles": perms
+                .permission_overwrites
+                .into_iter()
+                .map(|o| value_map! {
+                    "role_id": o.role_id.to_string(),
+                    "allow": decode_permissions(o.allow),
+                    "deny": decode_permissions(o.deny)
+                })
+                .collect::<Vec<_>>(),
+            "users": perms
+                .permission_users
+                .into_iter()
+                .map(|o| value_map! {
+                    "user_id": o.user.id,
+                    "allow": decode_permissions(o.allow),
+                    "deny": decode_permissions(o.deny)
+                })
+                .collect::<Vec<_>>(),
+            "sync": perms.permission_sync != 0
+        }
+        .into())
+    }
+
+    /// create the overwrite if absent, then update its allow and deny masks
+    pub async fn set_channel_permission(&self, c: SetChannelPermission) -> RespReault {
+        let allow = encode_permissions(c.allow.as_deref().unwrap_or_default())?;
+        let deny = encode_permissions(c.deny.as_deref().unwrap_or_default())?;
+        if allow & deny != 0 {
+            return Err(resp_error::bad_param("allow and deny overlap"));
+        }
+        let (ty, value) = permission_target(c.role_id, c.user_id)?;
+        let kook = self.kook()?;
+        let perms = kook
+            .get_channel_role_index(&c.channel_id)
+            .await
+            .map_err(kook_error)?;
+        let exists = match ty {
+            "role_id" => perms
+                .permission_overwrites
+                .iter()
+                .any(|o| o.role_id.to_string() == value),
+            _ => perms.permission_users.iter().any(|o| o.user.id == value),
+        };
+        if !exists {
+            kook.create_channel_role(&c.channel_id, ty, &value)
+                .await
+                .map_err(kook_error)?;
+        }
+        kook.update_channel_role(&c.channel_id, ty, &value, allow, deny)
+            .await
+            .map_err(kook_error)?;
+        Ok(value_map! {
+            "allow": decode_permissions(allow),
+            "deny": decode_permissions(deny)
+        }
+        .into())
+    }
+
+    pub async fn delete_channel_permission(&self, c: ChannelPermissionTarget) -> RespReault {
+        let (ty, value) = permission_target(c.role_id, c.user_id)?;
+        self.kook()?
+            .delete_channel_role(&c.channel_id, ty, &value)
+            .await
+            .map_err(kook_error)?;
+        Ok(().into())
+    }
+}
+
+impl KHandler {
+    pub async fn move_voice_users(&self, c: MoveVoiceUsers) -> RespReault {
+        if c.user_ids.is_empty() {
+            return Err(resp_error::bad_param("user_ids required"));
+        }
+        self.kook()?
+            .move_channel_user(&c.channel_id, &c.user_ids)
+            .await
+            .map_err(kook_error)?;
+        Ok(().into())
+    }
+
+    pub async fn kick_voice_user(&self, c: ChannelMember) -> RespReault {
+        self.kook()?
+            .kickout_channel_user(&c.channel_id, &c.user_id)
+            .await
+            .map_err(kook_error)?;
+        Ok(().into())
+    }
+}
+
+/// music software accepted by Kook
+const MUSIC_SOFTWARES: [&str; 3] = ["cloudmusic", "qqmusic", "kugou"];
+
+fn game_to_value(game: kook::prelude::Game) -> ValueMap {
+    value_map! {
+        "game_id": game.id,
+        "name": game.name,
+        "type": game.ty,
+        "icon": game.icon
+    }
+}
+
+impl KHandler {
+    pub async fn set_activity(&self, c: SetActivity) -> RespReault {
+        let kook = self.kook()?;
+        if let Some(game_id) = c.game_id {
+            kook.create_game_activity(game_id).await
+        } else {
+            let Some(title) = c.title else {
+                return Err(resp_error::bad_param("game_id or title required"));
+            };
+            let software = c.software.unwrap_or_else(|| MUSIC_SOFTWARES[0].to_owned());
+            if !MUSIC_SOFTWARES.contains(&software.as_str()) {
+                return Err(resp_error::unsupported_param(format!(
+                    "software:{}",
+                    software
+                )));
+            }
+            kook.create_music_activity(&software, c.singer.as_deref().unwrap_or_default(), &title)
+                .await
+        }
+        .map_err(kook_error)?;
+        Ok(().into())
+    }
+
+    pub async fn clear_activity(&self, c: ClearActivity) -> RespReault {
+        let data_type = match c.ty.as_deref() {
+            None | Some("game") => 1,
+            Some("music") => 2,
+            Some(ty) => return Err(resp_error::unsupported_param(format!("type:{}", ty))),
+        };
+        self.kook()?
+            .delete_activity(data_type)
+            .await
+            .map_err(kook_error)?;
+        Ok(().into())
+    }
+
+    pub async fn get_game_list(&self, c: GetGameList) -> RespReault {
+        let ty = match c.ty.as_deref() {
+            None | Some("all") => 0,
+            Some("user") => 1,
+            Some("system") => 2,
+            Some(ty) => return Err(resp_error::unsupported_param(format!("type:{}", ty))),
+        };
+        let games = self.kook()?.get_game_list(ty).await.map_err(kook_error)?;
+        Ok(games
+            .into_iter()
+            .map(game_to_value)
+            .collect::<Vec<_>>()
+            .into())
+    }
+
+    pub async fn create_game(&self, c: CreateGame) -> RespReault {
+        let game = self
+            .kook()?
+            .create_game(&c.name, c.icon.as_deref())
+            .await
+            .map_err(kook_error)?;
+        Ok(game_to_value(game).into())
+    }
+
+    pub async fn get_intimacy(&self, c: UserId) -> RespReault {
+        let intimacy = self
+            .kook()?
+            .get_intimacy(&c.user_id)
+            .await
+            .map_err(kook_error)?;
+        Ok(value_map! {
+            "user_id": c.user_id,
+            "score": intimacy.score,
+            "social_info": intimacy.social_info,
+            "img_url": intimacy.img_url,
+            "last_modify": intimacy.last_modify as f64
+        }
+        .into())
+    }
+
+    pub async fn update_intimacy(&self, c: UpdateIntimacy) -> RespReault {
+        if matches!(c.score, Some(s) if s == 0 || s > 2200) {
+            return Err(resp_error::bad_param("score must be within 1-2200"));
+        }
+        self.kook()?
+            .update_intimacy(
+                &c.user_id,
+                c.score,
+                c.social_info.as_deref(),
+                c.img_id.as_deref(),
+            )
+            .await
+            .map_err(kook_error)?;
+        Ok(().into())
+    }
+}
+
+/// invite durations in seconds accepted by Kook
+const INVITE_DURATIONS: [u32; 7] = [0, 1800, 3600, 21600, 43200, 86400, 604800];
+/// invite usage limits accepted by Kook
+const INVITE_MAX_USES: [i32; 7] = [-1, 1, 5, 10, 25, 50, 100];
+
+fn invite_target(guild_id: &Option<String>, channel_id: &Option<String>) -> Result<(), RespError> {
+    if guild_id.is_none() && channel_id.is_none() {
+        Err(resp_error::bad_param("guild_id or channel_id required"))
+    } else {
+        Ok(())
+    }
+}
+
+impl KHandler {
+    pub async fn create_invite(&self, c: CreateInvite) -> RespReault {
+        invite_target(&c.guild_id, &c.channel_id)?;
+        if let Some(d) = c.duration.filter(|d| !INVITE_DURATIONS.contains(d)) {
+            return Err(resp_error::bad_param(format!(
+                "duration:{} not in {:?}",
+                d, INVITE_DURATIONS
+            )));
+        }
+        if let Some(m) = c.max_uses.filter(|m| !INVITE_MAX_USES.contains(m)) {
+            return Err(resp_error::bad_param(format!(
+                "max_uses:{} not in {:?}",
+                m, INVITE_MAX_USES
+            )));
+        }
+        let invite = self
+            .kook()?
+            .create_invite(
+                c.guild_id.as_deref(),
+                c.channel_id.as_deref(),
+                c.duration,
+                c.max_uses,
+            )
+            .await
+            .map_err(kook_error)?;
+        Ok(value_map! {
+            "url": invite.url
+        }
+        .into())
+    }
+
+    pub async fn get_invite_list(&self, c: InviteTarget) -> RespReault {
+        invite_target(&c.guild_id, &c.channel_id)?;
+        let invites = self
+            .kook()?
+            .get_invite_list(c.guild_id.as_deref(), c.channel_id.as_deref())
+            .await
+            .map_err(kook_error)?;
+        Ok(invites
+            .into_iter()
+            .map(|i| {
+                value_map! {
+                    "url_code": i.url_code,
+                    "url": i.url,
+                    "guild_id": i.guild_id,
+                    "channel_id": i.channel_id,
+                    "user_id": i.user.id,
+                    "duration": i.duration,
+                    "remaining_uses": i.remaining_times,
+                    "expire_time": i.expire_time as f64
+                }
+            })
+            .collect::<Vec<_>>()
+            .into())
+    }
+
+    pub async fn delete_invite(&self, c: DeleteInvite) -> RespReault {
+        self.kook()?
+            .delete_invite(&c.url_code, c.guild_id.as_deref(), c.channel_id.as_deref())
+            .await
+            .map_err(kook_error)?;
+        Ok(().into())
+    }
+}
+
+impl KHandler {
+    pub async fn upload_file(&self, c: UploadFile) -> RespReault {
+        let data = match c.ty.as_str() {
+            "url" => {
+                let Some(ref url) = c.url else {
+                    return Err(resp_error::bad_param("url required"));
+                };
+                download(url, c.headers.as_ref()).await?
+            }
+            "path" => {
+                let Some(ref path) = c.path else {
+                    return Err(resp_error::bad_param("path required"));
+                };
+                read_local(path, &self.file_config()?.allowed_dirs).await?
+            }
+            "data" => {
+                let Some(OneBotBytes(data)) = c.data else {
+                    return Err(resp_error::bad_param("data required"));
+                };
+                data
+            }
+            ty => return Err(resp_error::unsupported_param(format!("type:{}", ty))),
+        };
+        self.upload_to_kook(c.name, data, c.sha256.as_deref()).await
+    }
+
+    async fn upload_to_kook(
+        &self,
+        name: String,
+        data: Vec<u8>,
+        sha256: Option<&str>,
+    ) -> RespReault {
+        verify_sha256(&data, sha256)?;
+        check_size(&data, self.file_config()?)?;
+        let mime = detect_mime(&data, Some(&name))
+            .unwrap_or_else(|| "application/octet-stream".to_owned());
+        let info = upload_cached(self.kook()?, &self.files, data, &mime, name).await?;
+        Ok(value_map! {
+            "file_id": info.sha256
+        }
+        .into())
+    }
+
+    pub async fn upload_file_fragmented(&self, c: UploadFileFragmented) -> RespReault {
+        let fragments = self.fragments()?;
+        match c.stage.as_str() {
+            "prepare" => {
+                let (Some(name), Some(total_size)) = (c.name, c.total_size) else {
+                    return Err(resp_error::bad_param("name and total_size required"));
+                };
+                if total_size < 0 {
+                    return Err(resp_error::bad_param("total_size must not be negative"));
+                }
+                check_len(total_size as u64, self.file_config()?)?;
+                let file_id = fragments.prepare(name, total_size as u64).await?;
+                Ok(value_map! {
+                    "file_id": file_id
+                }
+                .into())
+            }
+            "transfer" => {
+                let (Some(file_id), Some(offset), Some(OneBotBytes(data))) =
+                    (c.file_id, c.offset, c.data)
+                else {
+                    return Err(resp_error::bad_param("file_id, offset and data required"));
+                };
+                if offset < 0 {
+                    return Err(resp_error::bad_param("offset must not be negative"));
+                }
+                fragments.transfer(&file_id, offset as u64, &data).await?;
+                Ok(().into())
+            }
+            "finish" => {
+                let (Some(file_id), Some(sha256)) = (c.file_id, c.sha256) else {
+                    return Err(resp_error::bad_param("file_id and sha256 required"));
+                };
+                let (name, data) = fragments.finish(&file_id).await?;
+                self.upload_to_kook(name, data, Some(&sha256)).await
+            }
+            stage => Err(resp_error::unsupported_param(format!("stage:{}", stage))),
+        }
+    }
+}
+
+impl KHandler {
+    /// Kook asset url and file name of a file_id
+    /// only uploaded files and Kook assets are served, never arbitrary urls
+    fn file_url(&self, file_id: &str) -> Result<(String, String), RespError> {
+        match self.files.get(file_id) {
+            Some(info) => Ok((info.url, info.name)),
+            None if is_kook_asset(file_id) => Ok((file_id.to_owned(), url_file_name(file_id))),
+            None => Err(resp_error::bad_param(format!(
+                "unknown file_id:{}",
+                file_id
+            ))),
+        }
+    }
+
+    pub async fn get_file(&self, c: GetFile) -> RespReault {
+        let (url, name) = self.file_url(&c.file_id)?;
+        match c.ty.as_str() {
+            "url" => Ok(value_map! {
+                "name": name,
+                "url": url
+            }
+            .into()),
+            "path" => {
+                let path = self.cache()?.fetch(&url).await?;
+                let data = tokio::fs::read(&path)
+                    .await
+                    .map_err(resp_error::filesystem_error)?;
+                Ok(value_map! {
+                    "name": name,
+                    "path": path.to_string_lossy(),
+                    "sha256": sha256_hex(&data)
+                }
+                .into())
+            }
+            "data" => {
+                let path = self.cache()?.fetch(&url).await?;
+                let data = tokio::fs::read(&path)
+                    .await
+                    .map_err(resp_error::filesystem_error)?;
+                Ok(value_map! {
+                    "name": name,
+                    "sha256": sha256_hex(&data),
+                    "data": OneBotBytes(data)
+                }
+                .into())
+            }
+            ty => Err(resp_error::unsupported_param(format!("type:{}", ty))),
+        }
+    }
+
+    pub async fn get_file_fragmented(&self, c: GetFileFragmented) -> RespReault {
+        let (url, name) = self.file_url(&c.file_id)?;
+        let path = self.cache()?.fetch(&url).await?;
+        match c.stage.as_str() {
+            "prepare" => {
+                let data = tokio::fs::read(&path)
+                    .await
+                    .map_err(resp_error::filesystem_error)?;
+                Ok(value_map! {
+                    "name": name,
+                    "total_size": data.len() as i64,
+                    "sha256": sha256_hex(&data)
+                }
+                .into())
+            }
+            "transfer" => {
+                let (Some(offset), Some(size)) = (c.offset, c.size) else {
+                    return Err(resp_error::bad_param("offset and size required"));
+                };
+                if offset < 0 || size < 0 {
+                    return Err(resp_error::bad_param(
+                        "offset and size must not be negative",
+                    ));
+                }
+                let data = read_range(&path, offset as u64, size as u64).await?;
+                Ok(value_map! {
+                    "data": OneBotBytes(data)
+                }
+                .into())
+            }
+            stage => Err(resp_error::unsupported_param(format!("stage:{}", stage))),
+        }
+    }
+}
+
+impl KHandler {
+    pub async fn get_latest_events(&self, c: LatestEvents) -> RespReault {
+        let events = self
+            ._events
+            .get()
+            .ok_or(resp_error::bad_handler("Kook not inited"))?;
+        let events = events
+            .take(
+                c.consumer.as_deref().unwrap_or_default(),
+                c.limit.unwrap_or_default(),
+                c.timeout.unwrap_or_default(),
+            )
+            .await;
+        Ok(events.into())
+    }
+}
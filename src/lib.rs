@@ -1,7 +1,14 @@
+mod archive;
 mod config;
+mod dedup;
+mod events;
+mod file;
+mod gateway;
 mod handler;
 pub(crate) mod parse;
 pub mod structs;
+mod transcode;
+mod webhook;
 
 pub const WALLE_K: &str = "Walle-K";
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
@@ -1,13 +1,312 @@
 use walle_core::action::*;
-use walle_core::prelude::{PushToValueMap, TryFromAction};
+use walle_core::prelude::{PushToValueMap, TryFromAction, TryFromValue};
+use walle_core::util::OneBotBytes;
 
 #[derive(Debug, PushToValueMap, TryFromAction)]
 pub enum KookAction {
     // meta
-    GetLatestEvents(GetLatestEvents),
+    GetLatestEvents(LatestEvents),
     GetSupportedActions,
     GetStatus,
     GetVersion,
     // message
     SendMessage(SendMessage),
+    #[action(rename = "kook.get_message")]
+    GetMessage(GetMessage),
+    #[action(rename = "kook.get_message_list")]
+    GetMessageList(GetMessageList),
+    // file
+    UploadFile(UploadFile),
+    UploadFileFragmented(UploadFileFragmented),
+    GetFile(GetFile),
+    GetFileFragmented(GetFileFragmented),
+    // user
+    GetFriendList,
+    #[action(rename = "kook.create_user_chat")]
+    CreateUserChat(UserId),
+    #[action(rename = "kook.delete_user_chat")]
+    DeleteUserChat(ChatCode),
+    // guild
+    GetGuildMemberInfo(GetGuildMemberInfo),
+    // guild emoji
+    #[action(rename = "kook.get_guild_emoji_list")]
+    GetGuildEmojiList(GuildId),
+    #[action(rename = "kook.create_guild_emoji")]
+    CreateGuildEmoji(CreateGuildEmoji),
+    #[action(rename = "kook.update_guild_emoji")]
+    UpdateGuildEmoji(UpdateGuildEmoji),
+    #[action(rename = "kook.delete_guild_emoji")]
+    DeleteGuildEmoji(EmojiId),
+    // moderation
+    #[action(rename = "kook.kick_guild_member")]
+    KickGuildMember(GuildMember),
+    #[action(rename = "kook.get_blacklist")]
+    GetBlacklist(GuildId),
+    #[action(rename = "kook.add_blacklist")]
+    AddBlacklist(AddBlacklist),
+    #[action(rename = "kook.remove_blacklist")]
+    RemoveBlacklist(GuildMember),
+    #[action(rename = "kook.mute_guild_member")]
+    MuteGuildMember(MuteGuildMember),
+    #[action(rename = "kook.unmute_guild_member")]
+    UnmuteGuildMember(MuteGuildMember),
+    #[action(rename = "kook.get_muted_members")]
+    GetMutedMembers(GuildId),
+    #[action(rename = "kook.set_guild_member_nickname")]
+    SetGuildMemberNickname(SetGuildMemberNickname),
+    // channel permission
+    #[action(rename = "kook.get_channel_permissions")]
+    GetChannelPermissions(ChannelId),
+    #[action(rename = "kook.set_channel_permission")]
+    SetChannelPermission(SetChannelPermission),
+    #[action(rename = "kook.delete_channel_permission")]
+    DeleteChannelPermission(ChannelPermissionTarget),
+    // voice
+    #[action(rename = "kook.move_voice_users")]
+    MoveVoiceUsers(MoveVoiceUsers),
+    #[action(rename = "kook.kick_voice_user")]
+    KickVoiceUser(ChannelMember),
+    // activity
+    #[action(rename = "kook.set_activity")]
+    SetActivity(SetActivity),
+    #[action(rename = "kook.clear_activity")]
+    ClearActivity(ClearActivity),
+    #[action(rename = "kook.get_game_list")]
+    GetGameList(GetGameList),
+    #[action(rename = "kook.create_game")]
+    CreateGame(CreateGame),
+    // intimacy
+    #[action(rename = "kook.get_intimacy")]
+    GetIntimacy(UserId),
+    #[action(rename = "kook.update_intimacy")]
+    UpdateIntimacy(UpdateIntimacy),
+    // invite
+    #[action(rename = "kook.create_invite")]
+    CreateInvite(CreateInvite),
+    #[action(rename = "kook.get_invite_list")]
+    GetInviteList(InviteTarget),
+    #[action(rename = "kook.delete_invite")]
+    DeleteInvite(DeleteInvite),
+}
+
+#[derive(Debug, TryFromValue, PushToValueMap)]
+pub struct GetMessage {
+    pub message_id: String,
+    /// channel | private, default to channel
+    pub detail_type: Option<String>,
+    /// private message requires user_id or chat_code
+    pub user_id: Option<String>,
+    pub chat_code: Option<String>,
+}
+
+#[derive(Debug, TryFromValue, PushToValueMap)]
+pub struct GetMessageList {
+    /// channel | private
+    pub detail_type: String,
+    pub channel_id: Option<String>,
+    pub user_id: Option<String>,
+    pub chat_code: Option<String>,
+    /// message_id cursor, messages before it
+    pub before: Option<String>,
+    /// message_id cursor, messages after it
+    pub after: Option<String>,
+    /// 1-100, default to 50
+    pub limit: Option<u8>,
+}
+
+#[derive(Debug, TryFromValue, PushToValueMap)]
+pub struct UserId {
+    pub user_id: String,
+}
+
+#[derive(Debug, TryFromValue, PushToValueMap)]
+pub struct ChatCode {
+    pub chat_code: String,
+}
+
+#[derive(Debug, TryFromValue, PushToValueMap)]
+pub struct GuildId {
+    pub guild_id: String,
+}
+
+#[derive(Debug, TryFromValue, PushToValueMap)]
+pub struct GuildMember {
+    pub guild_id: String,
+    pub user_id: String,
+}
+
+#[derive(Debug, TryFromValue, PushToValueMap)]
+pub struct CreateGuildEmoji {
+    pub guild_id: String,
+    pub name: Option<String>,
+    pub url: Option<String>,
+    pub path: Option<String>,
+    pub bytes: Option<OneBotBytes>,
+}
+
+#[derive(Debug, TryFromValue, PushToValueMap)]
+pub struct UpdateGuildEmoji {
+    pub emoji_id: String,
+    pub name: String,
+}
+
+#[derive(Debug, TryFromValue, PushToValueMap)]
+pub struct EmojiId {
+    pub emoji_id: String,
+}
+
+#[derive(Debug, TryFromValue, PushToValueMap)]
+pub struct AddBlacklist {
+    pub guild_id: String,
+    pub user_id: String,
+    pub reason: Option<String>,
+    /// delete messages sent by the user in the last n days (0-7)
+    pub delete_message_days: Option<u8>,
+}
+
+#[derive(Debug, TryFromValue, PushToValueMap)]
+pub struct MuteGuildMember {
+    pub guild_id: String,
+    pub user_id: String,
+    /// mic | headset, default to mic
+    pub ty: Option<String>,
+}
+
+#[derive(Debug, TryFromValue, PushToValueMap)]
+pub struct SetGuildMemberNickname {
+    pub guild_id: String,
+    /// the bot itself when absent
+    pub user_id: Option<String>,
+    /// reset to the default nickname when absent or empty
+    pub nickname: Option<String>,
+}
+
+/// either guild_id or channel_id is required
+#[derive(Debug, TryFromValue, PushToValueMap)]
+pub struct InviteTarget {
+    pub guild_id: Option<String>,
+    pub channel_id: Option<String>,
+}
+
+#[derive(Debug, TryFromValue, PushToValueMap)]
+pub struct CreateInvite {
+    pub guild_id: Option<String>,
+    pub channel_id: Option<String>,
+    /// seconds, 0 for never expire
+    pub duration: Option<u32>,
+    /// -1 for unlimited
+    pub max_uses: Option<i32>,
+}
+
+#[derive(Debug, TryFromValue, PushToValueMap)]
+pub struct DeleteInvite {
+    pub url_code: String,
+    pub guild_id: Option<String>,
+    pub channel_id: Option<String>,
+}
+
+#[derive(Debug, TryFromValue, PushToValueMap)]
+pub struct ChannelId {
+    pub channel_id: String,
+}
+
+/// either role_id or user_id is required
+#[derive(Debug, TryFromValue, PushToValueMap)]
+pub struct ChannelPermissionTarget {
+    pub channel_id: String,
+    pub role_id: Option<String>,
+    pub user_id: Option<String>,
+}
+
+#[derive(Debug, TryFromValue, PushToValueMap)]
+pub struct SetChannelPermission {
+    pub channel_id: String,
+    pub role_id: Option<String>,
+    pub user_id: Option<String>,
+    /// permission names, see `PERMISSIONS`
+    pub allow: Option<Vec<String>>,
+    pub deny: Option<Vec<String>>,
+}
+
+#[derive(Debug, TryFromValue, PushToValueMap)]
+pub struct ChannelMember {
+    pub channel_id: String,
+    pub user_id: String,
+}
+
+#[derive(Debug, TryFromValue, PushToValueMap)]
+pub struct MoveVoiceUsers {
+    /// target voice channel
+    pub channel_id: String,
+    pub user_ids: Vec<String>,
+}
+
+/// playing game when game_id present, otherwise listening music
+#[derive(Debug, TryFromValue, PushToValueMap)]
+pub struct SetActivity {
+    pub game_id: Option<i64>,
+    /// cloudmusic | qqmusic | kugou, default to cloudmusic
+    pub software: Option<String>,
+    pub singer: Option<String>,
+    pub title: Option<String>,
+}
+
+#[derive(Debug, TryFromValue, PushToValueMap)]
+pub struct ClearActivity {
+    /// game | music, default to game
+    pub ty: Option<String>,
+}
+
+#[derive(Debug, TryFromValue, PushToValueMap)]
+pub struct GetGameList {
+    /// all | user | system, default to all
+    pub ty: Option<String>,
+}
+
+#[derive(Debug, TryFromValue, PushToValueMap)]
+pub struct CreateGame {
+    pub name: String,
+    pub icon: Option<String>,
+}
+
+#[derive(Debug, TryFromValue, PushToValueMap)]
+pub struct UpdateIntimacy {
+    pub user_id: String,
+    /// 1-2200
+    pub score: Option<u32>,
+    pub social_info: Option<String>,
+    pub img_id: Option<String>,
+}
+
+/// OneBot 12 upload_file_fragmented, fields required depend on stage
+#[derive(Debug, TryFromValue, PushToValueMap)]
+pub struct UploadFileFragmented {
+    /// prepare | transfer | finish
+    pub stage: String,
+    pub name: Option<String>,
+    pub total_size: Option<i64>,
+    pub file_id: Option<String>,
+    pub offset: Option<i64>,
+    pub data: Option<OneBotBytes>,
+    pub sha256: Option<String>,
+}
+
+/// OneBot 12 get_file_fragmented, fields required depend on stage
+#[derive(Debug, TryFromValue, PushToValueMap)]
+pub struct GetFileFragmented {
+    /// prepare | transfer
+    pub stage: String,
+    pub file_id: String,
+    pub offset: Option<i64>,
+    pub size: Option<i64>,
+}
+
+#[derive(Debug, TryFromValue, PushToValueMap)]
+pub struct LatestEvents {
+    /// <= 0 for all
+    pub limit: Option<i64>,
+    /// seconds to wait when there is no event
+    pub timeout: Option<i64>,
+    /// clients pulling separately each receive every event
+    pub consumer: Option<String>,
 }
@@ -1,16 +1,28 @@
 use std::sync::Arc;
 
-use kook::{kmd_from_str, prelude::AssetUrl, KMDItem, Kook, MessageType};
+use kook::{
+    kmd_from_str,
+    prelude::{AssetUrl, Attachment, ChannelMessage},
+    KMDItem, Kook, MessageType,
+};
 use walle_core::{
     error::WalleResult,
     prelude::{PushToValueMap, ToMsgSegment},
     resp::{resp_error, RespError},
-    segment::{Image, MsgSegment, Segments, Text},
-    util::OneBotBytes,
+    segment::{Image, MessageExt, MsgSegment, Segments, Text},
+    util::{OneBotBytes, Value, ValueMap},
     value_map, WalleError,
 };
 
-use crate::structs::WKSegment;
+use crate::{
+    file::{
+        decode_data_uri, detect_mime, download_capped, file_error, file_name, is_kook_asset,
+        read_local, sha256_hex, url_file_name, FileInfo, FileStore, FILE_REJECTED,
+    },
+    structs::WKSegment,
+    transcode::{check_size, prepare_image},
+    FileConfig,
+};
 
 #[derive(Debug, ToMsgSegment, PushToValueMap)]
 pub enum KookSegment {
@@ -18,7 +30,11 @@ pub enum KookSegment {
     Image(Image),
 }
 
-pub fn kook_message_to_segment(s: &str, ty: u8) -> WalleResult<Segments> {
+pub fn kook_message_to_segment(
+    s: &str,
+    ty: u8,
+    attachment: Option<&Attachment>,
+) -> WalleResult<Segments> {
     match ty {
         1 => Ok(vec![MsgSegment {
             ty: "text".to_owned(),
@@ -26,34 +42,93 @@ pub fn kook_message_to_segment(s: &str, ty: u8) -> WalleResult<Segments> {
                 "text": s
             },
         }]),
-        2 => Ok(vec![MsgSegment {
-            ty: "image".to_owned(),
-            data: value_map! {
-                "file_id": s,
-                "url": s
-            },
-        }]),
-        3 => Ok(vec![MsgSegment {
-            ty: "video".to_owned(),
-            data: value_map! {
-                "file_id": s,
-                "url": s
-            },
-        }]),
-        4 => Ok(vec![MsgSegment {
-            ty: "file".to_owned(),
-            data: value_map! {
-                "file_id": s,
-                "url": s
-            },
-        }]),
-        8 => todo!(), // audio
+        2 => Ok(vec![media_segment("image", s, attachment)]),
+        3 => Ok(vec![media_segment("video", s, attachment)]),
+        4 => Ok(vec![media_segment("file", s, attachment)]),
+        8 => Ok(vec![media_segment("audio", s, attachment)]),
         9 => Ok(kmds_to_segments(kmd_from_str(s))),
-        10 => todo!(), //card
-        _ => unreachable!(),
+        10 => Err(WalleError::Other("card message unsupported".to_owned())), //todo
+        ty => Err(WalleError::Other(format!("unknown message type:{}", ty))),
+    }
+}
+
+/// media segment with metadata from message attachment
+fn media_segment(ty: &str, url: &str, attachment: Option<&Attachment>) -> MsgSegment {
+    let mut data = value_map! {
+        "file_id": url,
+        "url": url
+    };
+    if let Some(a) = attachment {
+        let mut insert = |k: &str, v: Value| {
+            data.insert(k.to_owned(), v);
+        };
+        if let Some(ref name) = a.name {
+            insert("name", name.clone().into());
+        }
+        if let Some(size) = a.size {
+            insert("size", (size as i64).into());
+        }
+        if let Some(ref mime) = a.file_type {
+            insert("mime", mime.clone().into());
+        }
+        if let Some(width) = a.width {
+            insert("width", (width as i64).into());
+        }
+        if let Some(height) = a.height {
+            insert("height", (height as i64).into());
+        }
+        if let Some(duration) = a.duration {
+            insert("duration", (duration as f64).into());
+        }
+    }
+    MsgSegment {
+        ty: ty.to_owned(),
+        data,
     }
 }
 
+/// convert a message fetched from Kook message history to the same shape as message events
+/// history message in the shape of message events, card and other unsupported
+/// messages have no segments and keep their raw content in `kook.content`
+pub fn history_message_to_value(message: ChannelMessage) -> ValueMap {
+    let (segs, alt, raw) =
+        match kook_message_to_segment(&message.content, message.ty, message.attachments.as_ref()) {
+            Ok(segs) => {
+                let alt = segs.extract_plain_text();
+                (segs, alt, None)
+            }
+            Err(_) => {
+                let alt = if message.ty == 10 {
+                    "[card]"
+                } else {
+                    "[unsupported]"
+                };
+                (vec![], alt.to_owned(), Some(message.content))
+            }
+        };
+    let mut value = value_map! {
+        "message_id": message.id,
+        "time": message.create_at as f64,
+        "alt_message": alt,
+        "message": segs,
+        "user_id": message.author.id,
+        "kook.reactions": message
+            .reactions
+            .into_iter()
+            .map(|r| value_map! {
+                "emoji_id": r.emoji.id,
+                "emoji_name": r.emoji.name,
+                "count": r.count,
+                "me": r.me
+            })
+            .collect::<Vec<_>>()
+    };
+    if let Some(raw) = raw {
+        value.insert("kook.content".to_owned(), raw.into());
+    }
+    value
+}
+
 pub fn kmds_to_segments(v: Vec<KMDItem>) -> Segments {
     v.into_iter().map(kmd_to_segment).collect()
 }
@@ -169,10 +244,12 @@ fn segments_parse(segments: Segments) -> Result<Vec<WKSegment>, RespError> {
 
 pub async fn segments_to_str(
     kook: &Arc<Kook>,
+    files: &FileStore,
+    config: &FileConfig,
     segments: Segments,
 ) -> Result<(String, MessageType), RespError> {
     let mut segments = segments_parse(segments)?;
-    let (mut images, video, file) = segments_extrac(kook, &mut segments).await?;
+    let (mut images, video, file) = segments_extrac(kook, files, config, &mut segments).await?;
     if images.len() == 1 {
         return Ok((images.remove(0), MessageType::Image));
     } else if !images.is_empty() {
@@ -253,65 +330,179 @@ pub fn segments_to_kmd(segments: Vec<WKSegment>) -> Result<Vec<KMDItem>, RespErr
     Ok(kmds)
 }
 
+/// image, video or file segment to send
+struct Media {
+    file_id: String,
+    url: Option<String>,
+    bytes: Option<OneBotBytes>,
+    path: Option<String>,
+    name: Option<String>,
+    mime: Option<String>,
+}
+
 async fn segments_extrac(
     kook: &Arc<Kook>,
+    files: &FileStore,
+    config: &FileConfig,
     segments: &mut Vec<WKSegment>,
 ) -> Result<(Vec<String>, String, String), RespError> {
     let mut index = 0;
     let mut vs = (vec![], String::default(), String::default());
     while index < segments.len() {
         macro_rules! if_chain {
-            ($item: tt, $index: tt, $content: expr) => {
+            ($item: tt, $kind: expr, $default_mime: expr, $push: expr) => {
                 if let Some(WKSegment::$item { .. }) = segments.get(index) {
                     let WKSegment::$item {
-                                        file_id,
-                                        url,
-                                        bytes,
-                                    } = segments.remove(index) else { unreachable!() };
-                    if !file_id.is_empty() {
-                        vs.$index = file_id;
-                    } else if let Some(url) = url {
-                        vs.$index = url;
-                    } else if let Some(OneBotBytes(v)) = bytes {
-                        let AssetUrl { url } = kook
-                            .create_asset(v, $content, if file_id.is_empty() { "-" } else { &file_id })
-                            .await
-                            .map_err(|e| resp_error::bad_handler(e))?;
-                        vs.$index = url;
+                        file_id,
+                        url,
+                        bytes,
+                        path,
+                        name,
+                        mime,
+                    } = segments.remove(index)
+                    else {
+                        unreachable!()
+                    };
+                    let media = Media {
+                        file_id,
+                        url,
+                        bytes,
+                        path,
+                        name,
+                        mime,
+                    };
+                    if let Some(url) =
+                        media_url(kook, files, config, media, $kind, $default_mime).await?
+                    {
+                        $push(url);
                     }
                     continue;
                 }
             };
         }
-        if let Some(WKSegment::Image { .. }) = segments.get(index) {
-            let WKSegment::Image {
-                                file_id,
-                                url,
-                                bytes,
-                            } = segments.remove(index) else { unreachable!() };
-            if !file_id.is_empty() {
-                vs.0.push(file_id)
-            } else if let Some(url) = url {
-                vs.0.push(url)
-            } else if let Some(OneBotBytes(v)) = bytes {
-                let AssetUrl { url } = kook
-                    .create_asset(
-                        v,
-                        "image/png",
-                        if file_id.is_empty() { "-" } else { &file_id },
-                    )
-                    .await
-                    .map_err(|e| {
-                        println!("{}", e);
-                        resp_error::bad_handler(e)
-                    })?;
-                vs.0.push(url)
-            }
-            continue;
-        }
-        if_chain!(Video, 1, "video/mpeg4");
-        if_chain!(File, 2, "application/octet-stream");
+        if_chain!(Image, "image", "image/png", |url| vs.0.push(url));
+        if_chain!(Video, "video", "video/mp4", |url| vs.1 = url);
+        if_chain!(File, "file", "application/octet-stream", |url| vs.2 = url);
         index += 1
     }
     Ok(vs)
 }
+
+/// Kook asset url of a media segment, uploading its content when needed
+async fn media_url(
+    kook: &Arc<Kook>,
+    files: &FileStore,
+    config: &FileConfig,
+    media: Media,
+    kind: &str,
+    default_mime: &str,
+) -> Result<Option<String>, RespError> {
+    let Media {
+        file_id,
+        url,
+        bytes,
+        path,
+        mut name,
+        mut mime,
+    } = media;
+    if !file_id.is_empty() {
+        return files.resolve(file_id).map(Some);
+    }
+    let data = if let Some(url) = url {
+        if let Some((uri_mime, data)) = decode_data_uri(&url)? {
+            mime = mime.or(uri_mime);
+            data
+        } else if config.reupload_external && !is_kook_asset(&url) {
+            name = name.or_else(|| Some(url_file_name(&url)));
+            download_capped(&url, config.max_upload_size).await?
+        } else {
+            return Ok(Some(url));
+        }
+    } else if let Some(OneBotBytes(v)) = bytes {
+        v
+    } else if let Some(path) = path {
+        let data = read_local(&path, &config.allowed_dirs).await?;
+        name = name.or_else(|| {
+            std::path::Path::new(&path)
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+        });
+        data
+    } else {
+        return Ok(None);
+    };
+    let mime = mime
+        .or_else(|| detect_mime(&data, name.as_deref()))
+        .unwrap_or_else(|| default_mime.to_owned());
+    let (data, mime) = if kind == "image" {
+        prepare_image(data, mime, config).await?
+    } else {
+        (data, mime)
+    };
+    check_size(&data, config)?;
+    let name = file_name(&data, name, kind);
+    Ok(Some(
+        upload_cached(kook, files, data, &mime, name).await?.url,
+    ))
+}
+
+pub(crate) async fn upload_bytes(
+    kook: &Arc<Kook>,
+    bytes: Vec<u8>,
+    content_type: &str,
+    name: &str,
+) -> Result<String, RespError> {
+    let AssetUrl { url } = kook
+        .create_asset(
+            bytes,
+            content_type,
+            if name.is_empty() { "-" } else { name },
+        )
+        .await
+        .map_err(|e| file_error(FILE_REJECTED, format!("upload {} failed:{}", name, e)))?;
+    Ok(url)
+}
+
+/// load media content from `bytes`, local `path` or remote `url` in order
+pub(crate) async fn load_bytes(
+    config: &FileConfig,
+    url: Option<String>,
+    path: Option<String>,
+    bytes: Option<OneBotBytes>,
+) -> Result<Vec<u8>, RespError> {
+    if let Some(OneBotBytes(v)) = bytes {
+        Ok(v)
+    } else if let Some(path) = path {
+        read_local(&path, &config.allowed_dirs).await
+    } else if let Some(url) = url {
+        match decode_data_uri(&url)? {
+            Some((_, data)) => Ok(data),
+            None => download_capped(&url, config.max_upload_size).await,
+        }
+    } else {
+        Err(resp_error::bad_param("one of bytes, path or url required"))
+    }
+}
+
+/// upload content unless the same content was uploaded before
+pub(crate) async fn upload_cached(
+    kook: &Arc<Kook>,
+    files: &FileStore,
+    bytes: Vec<u8>,
+    content_type: &str,
+    name: String,
+) -> Result<FileInfo, RespError> {
+    let sha256 = sha256_hex(&bytes);
+    if let Some(info) = files.lookup(&sha256) {
+        return Ok(info);
+    }
+    let url = upload_bytes(kook, bytes, content_type, &name).await?;
+    let info = FileInfo {
+        name,
+        url,
+        sha256,
+        last_used: 0,
+    };
+    files.insert(info.clone()).await;
+    Ok(info)
+}
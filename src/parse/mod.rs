@@ -3,8 +3,10 @@ use walle_core::{event::Event, segment::MessageExt, structs::Selft, util::ValueM
 
 mod action;
 mod message;
-pub use action::KookAction;
+mod permission;
+pub use action::*;
 pub use message::*;
+pub use permission::*;
 
 type KookEvent = _KookEvent<EventExtra>;
 
@@ -26,13 +28,14 @@ pub async fn event_parse(event: KookEvent, user_id: String) -> Option<Event> {
     };
     match event.extra {
         EventExtra::GroupMessage(ref g) => {
-            let segs = match kook_message_to_segment(&event.content, event.ty) {
-                Ok(segs) => segs,
-                Err(e) => {
-                    tracing::warn!(target: crate::WALLE_K, "parse segs error:{}", e);
-                    return None;
-                }
-            };
+            let segs =
+                match kook_message_to_segment(&event.content, event.ty, g.attachments.as_ref()) {
+                    Ok(segs) => segs,
+                    Err(e) => {
+                        tracing::warn!(target: crate::WALLE_K, "parse segs error:{}", e);
+                        return None;
+                    }
+                };
             build_event(
                 &event,
                 ("message", "channel", ""),
@@ -48,13 +51,14 @@ pub async fn event_parse(event: KookEvent, user_id: String) -> Option<Event> {
             )
         }
         EventExtra::PersonMessage(p) => {
-            let segs = match kook_message_to_segment(&event.content, event.ty) {
-                Ok(segs) => segs,
-                Err(e) => {
-                    tracing::warn!(target: crate::WALLE_K, "parse segs error:{}", e);
-                    return None;
-                }
-            };
+            let segs =
+                match kook_message_to_segment(&event.content, event.ty, p.attachments.as_ref()) {
+                    Ok(segs) => segs,
+                    Err(e) => {
+                        tracing::warn!(target: crate::WALLE_K, "parse segs error:{}", e);
+                        return None;
+                    }
+                };
             Some(Event {
                 id: event.msg_id.clone(),
                 time: event.msg_timestamp as f64,
@@ -226,6 +230,110 @@ pub async fn event_parse(event: KookEvent, user_id: String) -> Option<Event> {
                 "operator_id": event.author_id
             },
         }),
+        EventExtra::System(SystemExtra::AddedEmoji { id, name, .. }) => build_event(
+            &event,
+            ("notice", "kook.emoji_add", ""),
+            value_map! {
+                "guild_id": event.target_id,
+                "emoji_id": id,
+                "emoji_name": name,
+                "self": selft
+            },
+        ),
+        EventExtra::System(SystemExtra::UpdatedEmoji { id, name, .. }) => build_event(
+            &event,
+            ("notice", "kook.emoji_update", ""),
+            value_map! {
+                "guild_id": event.target_id,
+                "emoji_id": id,
+                "emoji_name": name,
+                "self": selft
+            },
+        ),
+        EventExtra::System(SystemExtra::RemovedEmoji { id, name, .. }) => build_event(
+            &event,
+            ("notice", "kook.emoji_remove", ""),
+            value_map! {
+                "guild_id": event.target_id,
+                "emoji_id": id,
+                "emoji_name": name,
+                "self": selft
+            },
+        ),
+        EventExtra::System(SystemExtra::UserUpdated {
+            user_id,
+            username,
+            avatar,
+        }) => build_event(
+            &event,
+            ("notice", "kook.user_update", ""),
+            value_map! {
+                "user_id": user_id,
+                "username": username,
+                "avatar": avatar,
+                "self": selft
+            },
+        ),
+        EventExtra::System(SystemExtra::GuildMemberOnline {
+            user_id, guilds, ..
+        }) => build_event(
+            &event,
+            ("notice", "kook.member_online", ""),
+            value_map! {
+                "user_id": user_id,
+                "guild_ids": guilds,
+                "self": selft
+            },
+        ),
+        EventExtra::System(SystemExtra::GuildMemberOffline {
+            user_id, guilds, ..
+        }) => build_event(
+            &event,
+            ("notice", "kook.member_offline", ""),
+            value_map! {
+                "user_id": user_id,
+                "guild_ids": guilds,
+                "self": selft
+            },
+        ),
+        EventExtra::System(SystemExtra::UpdatedGuildMember { user_id, nickname }) => build_event(
+            &event,
+            ("notice", "kook.member_update", ""),
+            value_map! {
+                "guild_id": event.target_id,
+                "user_id": user_id,
+                "nickname": nickname,
+                "self": selft
+            },
+        ),
+        EventExtra::System(SystemExtra::AddedBlockList {
+            operator_id,
+            remark,
+            user_id,
+        }) => build_event(
+            &event,
+            ("notice", "kook.blacklist_add", ""),
+            value_map! {
+                "guild_id": event.target_id,
+                "user_ids": user_id,
+                "operator_id": operator_id,
+                "reason": remark,
+                "self": selft
+            },
+        ),
+        EventExtra::System(SystemExtra::DeletedBlockList {
+            operator_id,
+            user_id,
+        }) => build_event(
+            &event,
+            ("notice", "kook.blacklist_remove", ""),
+            value_map! {
+                "guild_id": event.target_id,
+                "user_ids": user_id,
+                "operator_id": operator_id,
+                "self": selft
+            },
+        ),
         _ => None,
     }
 }
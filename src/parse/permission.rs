@@ -0,0 +1,84 @@
+use walle_core::resp::{resp_error, RespError};
+
+/// Kook permission names indexed by bit
+pub const PERMISSIONS: [&str; 28] = [
+    "administrator",
+    "manage_guild",
+    "view_audit_log",
+    "create_invite",
+    "manage_invite",
+    "manage_channel",
+    "kick_member",
+    "ban_member",
+    "manage_emoji",
+    "change_nickname",
+    "manage_role",
+    "view_channel",
+    "send_message",
+    "manage_message",
+    "upload_file",
+    "voice_connect",
+    "voice_manage",
+    "mention_everyone",
+    "add_reaction",
+    "follow_reaction",
+    "passive_connect",
+    "push_to_talk",
+    "free_speak",
+    "speak",
+    "deafen_member",
+    "mute_member",
+    "manage_nickname",
+    "play_music",
+];
+
+pub fn decode_permissions(mask: u64) -> Vec<&'static str> {
+    PERMISSIONS
+        .iter()
+        .enumerate()
+        .filter(|(bit, _)| mask & (1 << bit) != 0)
+        .map(|(_, name)| *name)
+        .collect()
+}
+
+pub fn encode_permissions(names: &[String]) -> Result<u64, RespError> {
+    names.iter().try_fold(0, |mask, name| {
+        match PERMISSIONS.iter().position(|p| p == name) {
+            Some(bit) => Ok(mask | (1 << bit)),
+            None => Err(resp_error::bad_param(format!(
+                "unknown permission:{}",
+                name
+            ))),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_by_bit() {
+        assert_eq!(decode_permissions(0), Vec::<&str>::new());
+        assert_eq!(
+            decode_permissions(1 | (1 << 12) | (1 << 27)),
+            vec!["administrator", "send_message", "play_music"]
+        );
+        // bits beyond known permissions are ignored
+        assert_eq!(decode_permissions(1 << 40), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn encode_round_trip() {
+        let names = vec!["view_channel".to_owned(), "speak".to_owned()];
+        let mask = encode_permissions(&names).unwrap();
+        assert_eq!(mask, (1 << 11) | (1 << 23));
+        assert_eq!(decode_permissions(mask), vec!["view_channel", "speak"]);
+        assert_eq!(encode_permissions(&[]).unwrap(), 0);
+    }
+
+    #[test]
+    fn encode_unknown_name() {
+        assert!(encode_permissions(&["fly".to_owned()]).is_err());
+    }
+}
@@ -25,15 +25,24 @@ pub enum WKSegment {
         file_id: String,
         url: Option<String>,
         bytes: Option<OneBotBytes>,
+        path: Option<String>,
+        name: Option<String>,
+        mime: Option<String>,
     },
     Video {
         file_id: String,
         url: Option<String>,
         bytes: Option<OneBotBytes>,
+        path: Option<String>,
+        name: Option<String>,
+        mime: Option<String>,
     },
     File {
         file_id: String,
         url: Option<String>,
         bytes: Option<OneBotBytes>,
+        path: Option<String>,
+        name: Option<String>,
+        mime: Option<String>,
     },
 }
@@ -0,0 +1,237 @@
+use std::io::Cursor;
+
+use image::{imageops::FilterType, DynamicImage, ImageOutputFormat};
+use walle_core::resp::{resp_error, RespError};
+
+use crate::{
+    file::{file_error, FILE_TOO_LARGE},
+    FileConfig,
+};
+
+/// image formats Kook displays directly
+const KOOK_IMAGE_MIMES: [&str; 4] = ["image/png", "image/jpeg", "image/gif", "image/webp"];
+
+/// reject content over `max_upload_size`
+pub fn check_size(data: &[u8], config: &FileConfig) -> Result<(), RespError> {
+    check_len(data.len() as u64, config)
+}
+
+/// check a declared file size before receiving its content
+pub fn check_len(len: u64, config: &FileConfig) -> Result<(), RespError> {
+    if config.max_upload_size > 0 && len > config.max_upload_size {
+        Err(file_error(
+            FILE_TOO_LARGE,
+            format!("file size {} exceeds limit {}", len, config.max_upload_size),
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// convert images Kook can not display, and downscale or recompress large images,
+/// returns the content and its content type to upload
+pub async fn prepare_image(
+    data: Vec<u8>,
+    mime: String,
+    config: &FileConfig,
+) -> Result<(Vec<u8>, String), RespError> {
+    let convert = !KOOK_IMAGE_MIMES.contains(&mime.as_str());
+    // gif is kept as is to keep animation
+    let compress = mime != "image/gif"
+        && config.image_compress_threshold > 0
+        && data.len() as u64 > config.image_compress_threshold;
+    let resize = mime != "image/gif" && config.image_max_dimension > 0;
+    if !convert && !compress && !resize {
+        return Ok((data, mime));
+    }
+    let config = config.clone();
+    tokio::task::spawn_blocking(move || transcode(data, mime, convert, compress, &config))
+        .await
+        .map_err(resp_error::internal_handler_error)?
+}
+
+fn transcode(
+    data: Vec<u8>,
+    mime: String,
+    convert: bool,
+    compress: bool,
+    config: &FileConfig,
+) -> Result<(Vec<u8>, String), RespError> {
+    let mut img = match image::load_from_memory(&data) {
+        Ok(img) => img,
+        Err(e) if convert => {
+            return Err(resp_error::unsupported_segment_data(format!(
+                "image type {} unsupported:{}",
+                mime, e
+            )))
+        }
+        Err(_) => return Ok((data, mime)),
+    };
+    let max = config.image_max_dimension;
+    let resized = max > 0 && (img.width() > max || img.height() > max);
+    if resized {
+        img = img.resize(max, max, FilterType::Lanczos3);
+    }
+    if !convert && !compress && !resized {
+        return Ok((data, mime));
+    }
+    let (format, mime) = if compress && !img.color().has_alpha() {
+        (ImageOutputFormat::Jpeg(config.image_quality), "image/jpeg")
+    } else {
+        (ImageOutputFormat::Png, "image/png")
+    };
+    if matches!(format, ImageOutputFormat::Jpeg(_)) {
+        img = DynamicImage::ImageRgb8(img.to_rgb8());
+    }
+    let mut out = Cursor::new(vec![]);
+    img.write_to(&mut out, format)
+        .map_err(|e| resp_error::internal_handler_error(format!("encode image failed:{}", e)))?;
+    let out = out.into_inner();
+    tracing::debug!(
+        target: crate::WALLE_K,
+        "transcoded image {} bytes to {} {} bytes",
+        data.len(),
+        mime,
+        out.len()
+    );
+    Ok((out, mime.to_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{ColorType, ImageFormat, Rgb, RgbImage, Rgba, RgbaImage};
+
+    use super::*;
+
+    fn encode(img: DynamicImage, format: ImageOutputFormat) -> Vec<u8> {
+        let mut out = Cursor::new(vec![]);
+        img.write_to(&mut out, format).unwrap();
+        out.into_inner()
+    }
+
+    /// pixels hard to compress, so the png is large enough to recompress
+    fn noisy(width: u32, height: u32) -> RgbImage {
+        RgbImage::from_fn(width, height, |x, y| {
+            let v = (x * 7919 + y * 104729) as u8;
+            Rgb([v, v.wrapping_mul(31), v.wrapping_add(97)])
+        })
+    }
+
+    #[tokio::test]
+    async fn bmp_to_png() {
+        let data = encode(DynamicImage::ImageRgb8(noisy(4, 4)), ImageOutputFormat::Bmp);
+        let (out, mime) = prepare_image(data, "image/bmp".to_owned(), &FileConfig::default())
+            .await
+            .unwrap();
+        assert_eq!(mime, "image/png");
+        assert_eq!(image::guess_format(&out).unwrap(), ImageFormat::Png);
+        let img = image::load_from_memory(&out).unwrap();
+        assert_eq!((img.width(), img.height()), (4, 4));
+    }
+
+    #[tokio::test]
+    async fn downscale() {
+        let data = encode(
+            DynamicImage::ImageRgb8(noisy(200, 100)),
+            ImageOutputFormat::Png,
+        );
+        let config = FileConfig {
+            image_max_dimension: 50,
+            ..Default::default()
+        };
+        let (out, mime) = prepare_image(data, "image/png".to_owned(), &config)
+            .await
+            .unwrap();
+        assert_eq!(mime, "image/png");
+        let img = image::load_from_memory(&out).unwrap();
+        assert_eq!((img.width(), img.height()), (50, 25));
+
+        // images within the bound are kept as is
+        let data = encode(
+            DynamicImage::ImageRgb8(noisy(40, 20)),
+            ImageOutputFormat::Png,
+        );
+        let (out, _) = prepare_image(data.clone(), "image/png".to_owned(), &config)
+            .await
+            .unwrap();
+        assert_eq!(out, data);
+    }
+
+    #[tokio::test]
+    async fn recompress_above_threshold() {
+        let data = encode(
+            DynamicImage::ImageRgb8(noisy(64, 64)),
+            ImageOutputFormat::Png,
+        );
+        let config = FileConfig {
+            image_compress_threshold: data.len() as u64 - 1,
+            ..Default::default()
+        };
+        let (out, mime) = prepare_image(data.clone(), "image/png".to_owned(), &config)
+            .await
+            .unwrap();
+        assert_eq!(mime, "image/jpeg");
+        assert_eq!(image::guess_format(&out).unwrap(), ImageFormat::Jpeg);
+
+        // not above the threshold
+        let config = FileConfig {
+            image_compress_threshold: data.len() as u64,
+            ..Default::default()
+        };
+        let (out, mime) = prepare_image(data.clone(), "image/png".to_owned(), &config)
+            .await
+            .unwrap();
+        assert_eq!(mime, "image/png");
+        assert_eq!(out, data);
+    }
+
+    #[tokio::test]
+    async fn alpha_kept_as_png() {
+        let img = RgbaImage::from_fn(64, 64, |x, y| Rgba([x as u8, y as u8, 0, (x * y) as u8]));
+        let data = encode(DynamicImage::ImageRgba8(img), ImageOutputFormat::Png);
+        let config = FileConfig {
+            image_compress_threshold: 1,
+            ..Default::default()
+        };
+        let (out, mime) = prepare_image(data, "image/png".to_owned(), &config)
+            .await
+            .unwrap();
+        assert_eq!(mime, "image/png");
+        let img = image::load_from_memory(&out).unwrap();
+        assert_eq!(img.color(), ColorType::Rgba8);
+    }
+
+    #[tokio::test]
+    async fn gif_passthrough() {
+        let data = encode(
+            DynamicImage::ImageRgba8(RgbaImage::new(100, 100)),
+            ImageOutputFormat::Gif,
+        );
+        let config = FileConfig {
+            image_compress_threshold: 1,
+            image_max_dimension: 10,
+            ..Default::default()
+        };
+        let (out, mime) = prepare_image(data.clone(), "image/gif".to_owned(), &config)
+            .await
+            .unwrap();
+        assert_eq!(mime, "image/gif");
+        assert_eq!(out, data);
+    }
+
+    #[test]
+    fn len_limit() {
+        let config = FileConfig {
+            max_upload_size: 10,
+            ..Default::default()
+        };
+        assert!(check_len(10, &config).is_ok());
+        assert_eq!(check_len(11, &config).unwrap_err().retcode, FILE_TOO_LARGE);
+        assert!(check_size(&[0; 11], &config).is_err());
+        let unlimited = FileConfig {
+            max_upload_size: 0,
+            ..Default::default()
+        };
+        assert!(check_len(u64::MAX, &unlimited).is_ok());
+    }
+}
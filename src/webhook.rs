@@ -0,0 +1,366 @@
+use std::{convert::Infallible, fmt::Display, io::Read, net::SocketAddr, sync::Arc};
+
+use aes::cipher::{block_padding::Pkcs7, BlockDecryptMut, KeyIvInit};
+use base64::Engine;
+use hyper::{
+    server::{conn::AddrIncoming, Builder},
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use serde_json::Value;
+
+use crate::gateway::{Gateway, GatewayState};
+use crate::WebhookConfig;
+
+type Aes256CbcDec = cbc::Decryptor<aes::Aes256>;
+
+/// max bytes of a callback body as received
+const MAX_BODY_SIZE: usize = 1024 * 1024;
+/// max bytes of a callback body after inflating
+const MAX_INFLATED_SIZE: usize = 8 * 1024 * 1024;
+
+#[derive(Debug)]
+pub enum WebhookError {
+    /// body is not a valid (compressed / encrypted) payload
+    Decode(String),
+    /// verify_token mismatch
+    Token,
+    /// body over size limit, before or after inflating
+    TooLarge,
+}
+
+impl Display for WebhookError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Decode(e) => write!(f, "decode failed:{}", e),
+            Self::Token => write!(f, "verify_token mismatch"),
+            Self::TooLarge => write!(f, "body too large"),
+        }
+    }
+}
+
+/// inflate zlib body, Kook compresses callbacks unless `compress=0` is in the url
+fn inflate(body: &[u8]) -> Result<Vec<u8>, WebhookError> {
+    if body.first() == Some(&b'{') {
+        return Ok(body.to_vec());
+    }
+    let mut data = vec![];
+    flate2::read::ZlibDecoder::new(body)
+        .take(MAX_INFLATED_SIZE as u64 + 1)
+        .read_to_end(&mut data)
+        .map_err(|e| WebhookError::Decode(e.to_string()))?;
+    if data.len() > MAX_INFLATED_SIZE {
+        return Err(WebhookError::TooLarge);
+    }
+    Ok(data)
+}
+
+/// `encrypt` is base64 of 16 bytes iv followed by base64 of the AES-256-CBC ciphertext,
+/// key is `encrypt_key` padded with `\0` to 32 bytes
+fn decrypt(encrypt_key: &str, encrypt: &str) -> Result<Value, WebhookError> {
+    let decode = |s: &[u8]| {
+        base64::engine::general_purpose::STANDARD
+            .decode(s)
+            .map_err(|e| WebhookError::Decode(e.to_string()))
+    };
+    let raw = decode(encrypt.as_bytes())?;
+    if raw.len() <= 16 {
+        return Err(WebhookError::Decode("encrypted data too short".to_owned()));
+    }
+    let mut data = decode(&raw[16..])?;
+    let mut key = [0u8; 32];
+    let len = encrypt_key.len().min(32);
+    key[..len].copy_from_slice(&encrypt_key.as_bytes()[..len]);
+    let plain = Aes256CbcDec::new_from_slices(&key, &raw[..16])
+        .map_err(|e| WebhookError::Decode(e.to_string()))?
+        .decrypt_padded_mut::<Pkcs7>(&mut data)
+        .map_err(|e| WebhookError::Decode(e.to_string()))?;
+    serde_json::from_slice(plain).map_err(|e| WebhookError::Decode(e.to_string()))
+}
+
+/// decode a callback body into its signalling frame and check verify_token,
+/// bodies must be encrypted when `encrypt_key` is set
+pub fn decode_body(config: &WebhookConfig, body: &[u8]) -> Result<Value, WebhookError> {
+    let data = inflate(body)?;
+    let mut frame: Value =
+        serde_json::from_slice(&data).map_err(|e| WebhookError::Decode(e.to_string()))?;
+    match frame.get("encrypt").and_then(|e| e.as_str()) {
+        Some(encrypt) => frame = decrypt(&config.encrypt_key, encrypt)?,
+        None if !config.encrypt_key.is_empty() => {
+            return Err(WebhookError::Decode("body not encrypted".to_owned()))
+        }
+        None => {}
+    }
+    if config.verify_token.is_empty()
+        || frame["d"]["verify_token"].as_str() != Some(config.verify_token.as_str())
+    {
+        return Err(WebhookError::Token);
+    }
+    Ok(frame)
+}
+
+/// embedded HTTP endpoint receiving Kook webhook callbacks
+pub struct Webhook {
+    config: WebhookConfig,
+    gateway: Arc<Gateway>,
+}
+
+impl Webhook {
+    pub fn new(config: WebhookConfig, gateway: Arc<Gateway>) -> Self {
+        Self { config, gateway }
+    }
+
+    /// bind the listening address, a webhook without verify_token would accept anyone
+    pub fn bind(&self) -> Result<Builder<AddrIncoming>, String> {
+        if self.config.verify_token.is_empty() {
+            return Err("Kook webhook requires verify_token".to_owned());
+        }
+        let addr = SocketAddr::new(self.config.host, self.config.port);
+        Server::try_bind(&addr).map_err(|e| format!("Kook webhook bind {} failed:{}", addr, e))
+    }
+
+    pub async fn serve(self: Arc<Self>, builder: Builder<AddrIncoming>) {
+        let webhook = self.clone();
+        let make_service = make_service_fn(move |_| {
+            let webhook = webhook.clone();
+            async move { Ok::<_, Infallible>(service_fn(move |req| webhook.clone().handle(req))) }
+        });
+        tracing::info!(
+            target: crate::WALLE_K,
+            "Kook webhook listening on http://{}{}",
+            SocketAddr::new(self.config.host, self.config.port),
+            self.config.path
+        );
+        self.gateway.set_state(GatewayState::Online);
+        if let Err(e) = builder.serve(make_service).await {
+            tracing::warn!(target: crate::WALLE_K, "Kook webhook server error:{}", e);
+        }
+        self.gateway.set_state(GatewayState::Offline);
+    }
+
+    async fn handle(self: Arc<Self>, req: Request<Body>) -> Result<Response<Body>, Infallible> {
+        if req.method() != Method::POST || req.uri().path() != self.config.path {
+            return Ok(response(StatusCode::NOT_FOUND, Body::empty()));
+        }
+        match read_body(req.into_body()).await {
+            Ok(body) => Ok(self.receive(&body)),
+            Err(e) => Ok(error_response(e)),
+        }
+    }
+
+    /// handle one callback body, answering the challenge or dispatching the event
+    pub fn receive(&self, body: &[u8]) -> Response<Body> {
+        let mut frame = match decode_body(&self.config, body) {
+            Ok(frame) => frame,
+            Err(e) => return error_response(e),
+        };
+        let d = frame["d"].take();
+        if d["type"].as_i64() == Some(255) && d["channel_type"] == "WEBHOOK_CHALLENGE" {
+            let challenge = serde_json::json!({ "challenge": d["challenge"] });
+            return response(StatusCode::OK, challenge.to_string().into());
+        }
+        if frame["s"].as_i64() == Some(0) {
+            self.gateway.dispatch(frame["sn"].as_i64(), d);
+        }
+        response(StatusCode::OK, Body::empty())
+    }
+}
+
+/// read the request body, refusing to buffer more than MAX_BODY_SIZE
+async fn read_body(mut body: Body) -> Result<Vec<u8>, WebhookError> {
+    use hyper::body::HttpBody;
+    if body.size_hint().lower() > MAX_BODY_SIZE as u64 {
+        return Err(WebhookError::TooLarge);
+    }
+    let mut data = vec![];
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.map_err(|e| WebhookError::Decode(e.to_string()))?;
+        if data.len() + chunk.len() > MAX_BODY_SIZE {
+            return Err(WebhookError::TooLarge);
+        }
+        data.extend_from_slice(&chunk);
+    }
+    Ok(data)
+}
+
+fn error_response(e: WebhookError) -> Response<Body> {
+    tracing::warn!(target: crate::WALLE_K, "Kook webhook {}", e);
+    let status = match e {
+        WebhookError::Decode(_) => StatusCode::BAD_REQUEST,
+        WebhookError::Token => StatusCode::FORBIDDEN,
+        WebhookError::TooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+    };
+    response(status, e.to_string().into())
+}
+
+fn response(status: StatusCode, body: Body) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "application/json")
+        .body(body)
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use kook::Kook;
+    use tokio::sync::mpsc;
+
+    use super::*;
+    use crate::{dedup::Dedup, KookConfig};
+
+    /// recorded channel text message callback
+    const MESSAGE: &str = r#"{"s":0,"d":{"channel_type":"GROUP","type":1,"target_id":"3934455131486017","author_id":"1558012187","content":"hello","msg_id":"67d3a3b7-1bd3-4c2b-a1a3-6fd4e1b3e7a2","msg_timestamp":1672915543245,"nonce":"","verify_token":"token","extra":{"type":1,"guild_id":"6318218364918390","channel_name":"general","mention":[],"mention_all":false,"mention_roles":[],"mention_here":false,"author":{"id":"1558012187","username":"tester","identify_num":"1234","online":true,"avatar":"https://img.kookapp.cn/avatars/a.png","vip_avatar":"https://img.kookapp.cn/avatars/a.png","nickname":"tester","roles":[],"bot":false,"status":1}}},"sn":7}"#;
+    /// MESSAGE compressed with zlib, base64 encoded
+    const MESSAGE_ZLIB: &str = "eJyVUctuhSAU/BfWehXB5w902aZJV01jUM9VIoJBvOlN47/3EI19rboBzpzhMDN8kIVUcUA6Un2QdhBag6rdfQZSkYfnx5cnEpC9pHgQtgdXSyQTVjLO05QyyosspjnyxOoGY/c2TdMipgktfKM12oF2CA+glEFkWvqdl+UdE6zJQ9p0LORt0oSCChZm144DbRjkIjn4Tk6wODHNqCXLkxKf4CzhaUC00a0XjMQbWHm9186MoBHZ94DAu7PCWzy99KtU3aGB0QKVsoyXuJaxV3wkocXkB/egwQrlhaAPaXD069tZ1EIpUl2FWuALs0bB8pM2gIWTt4flJf3Na13AHi87tAwWMdn5KWhNr5PnJ4wjarSSGnnOrn7mTeAX+Zidm5cqiuTUX0ZjRjHPl1ZHe3+JxGXWvQ9LzvV/72jZjr+1ffPaGHdaxM9yK+J02zasMLR8+wQQZMVv";
+    /// recorded challenge callback
+    const CHALLENGE: &str = r#"{"s":0,"d":{"type":255,"channel_type":"WEBHOOK_CHALLENGE","challenge":"bkqd1Z6Kpe1O","verify_token":"token"}}"#;
+    /// CHALLENGE encrypted with encrypt_key `secret`
+    const CHALLENGE_ENCRYPTED: &str = r#"{"encrypt":"MDEyMzQ1Njc4OWFiY2RlZjJtSXB5dzdyeG5hck5pdW9qRlNpTkY4VEhtV0o2V2cvN3FoNW9Ebkk1V2psRFhvUmo5YkNRUnd5UHdpSnlsQmFGSXdsRnRWT2JzUFhOc1dFbkdMQm9wcEV6cHR2M2ZPV2xYUGZiOTV1M0doTGlqMEx5MWJkZnBiTXh6VFlOU1VXeTE1Q2J1SkJuSkZGR3pGQmNMWVhOUT09"}"#;
+
+    fn config() -> WebhookConfig {
+        WebhookConfig {
+            verify_token: "token".to_owned(),
+            ..Default::default()
+        }
+    }
+
+    fn encrypted_config() -> WebhookConfig {
+        WebhookConfig {
+            encrypt_key: "secret".to_owned(),
+            ..config()
+        }
+    }
+
+    fn zlib(data: &str) -> Vec<u8> {
+        base64::engine::general_purpose::STANDARD
+            .decode(data)
+            .unwrap()
+    }
+
+    fn webhook(
+        config: WebhookConfig,
+    ) -> (Webhook, mpsc::UnboundedReceiver<crate::gateway::KookEvent>) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let kook = Arc::new(Kook::new_from_config(KookConfig::default(), tx.clone()));
+        let dedup = Dedup::new(Duration::from_secs(60), 100);
+        let gateway = Arc::new(Gateway::new(kook, tx, dedup));
+        (Webhook::new(config, gateway), rx)
+    }
+
+    async fn body_string(resp: Response<Body>) -> String {
+        let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    #[test]
+    fn decode_plain() {
+        let frame = decode_body(&config(), MESSAGE.as_bytes()).unwrap();
+        assert_eq!(frame["sn"], 7);
+        assert_eq!(frame["d"]["content"], "hello");
+    }
+
+    #[test]
+    fn decode_zlib() {
+        let frame = decode_body(&config(), &zlib(MESSAGE_ZLIB)).unwrap();
+        assert_eq!(frame, serde_json::from_str::<Value>(MESSAGE).unwrap());
+    }
+
+    #[test]
+    fn decode_encrypted() {
+        let frame = decode_body(&encrypted_config(), CHALLENGE_ENCRYPTED.as_bytes()).unwrap();
+        assert_eq!(frame, serde_json::from_str::<Value>(CHALLENGE).unwrap());
+        let wrong_key = WebhookConfig {
+            encrypt_key: "wrong".to_owned(),
+            ..config()
+        };
+        assert!(decode_body(&wrong_key, CHALLENGE_ENCRYPTED.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn plain_rejected_when_encrypted() {
+        for body in [MESSAGE.as_bytes().to_vec(), zlib(MESSAGE_ZLIB)] {
+            assert!(matches!(
+                decode_body(&encrypted_config(), &body),
+                Err(WebhookError::Decode(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn verify_token_required() {
+        let no_token = WebhookConfig::default();
+        let body = MESSAGE.replace(r#""verify_token":"token""#, r#""verify_token":"""#);
+        assert!(matches!(
+            decode_body(&no_token, body.as_bytes()),
+            Err(WebhookError::Token)
+        ));
+        let (webhook, _rx) = webhook(no_token);
+        assert!(webhook.bind().is_err());
+    }
+
+    #[test]
+    fn decode_rejects_garbage_and_bombs() {
+        assert!(matches!(
+            decode_body(&config(), b"not json"),
+            Err(WebhookError::Decode(_))
+        ));
+        let mut encoder = flate2::write::ZlibEncoder::new(vec![], flate2::Compression::best());
+        std::io::Write::write_all(&mut encoder, &vec![b' '; MAX_INFLATED_SIZE + 1]).unwrap();
+        let bomb = encoder.finish().unwrap();
+        assert!(bomb.len() < MAX_BODY_SIZE);
+        assert!(matches!(
+            decode_body(&config(), &bomb),
+            Err(WebhookError::TooLarge)
+        ));
+    }
+
+    #[tokio::test]
+    async fn challenge_reply() {
+        for (config, body) in [
+            (config(), CHALLENGE),
+            (encrypted_config(), CHALLENGE_ENCRYPTED),
+        ] {
+            let (webhook, _rx) = webhook(config);
+            let resp = webhook.receive(body.as_bytes());
+            assert_eq!(resp.status(), StatusCode::OK);
+            assert_eq!(body_string(resp).await, r#"{"challenge":"bkqd1Z6Kpe1O"}"#);
+        }
+    }
+
+    #[tokio::test]
+    async fn wrong_verify_token() {
+        let (webhook, mut rx) = webhook(config());
+        let body = MESSAGE.replace(r#""verify_token":"token""#, r#""verify_token":"other""#);
+        assert_eq!(
+            webhook.receive(body.as_bytes()).status(),
+            StatusCode::FORBIDDEN
+        );
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn event_dispatched() {
+        let (webhook, mut rx) = webhook(config());
+        assert_eq!(
+            webhook.receive(&zlib(MESSAGE_ZLIB)).status(),
+            StatusCode::OK
+        );
+        let event = rx.try_recv().unwrap();
+        assert_eq!(event.msg_id, "67d3a3b7-1bd3-4c2b-a1a3-6fd4e1b3e7a2");
+        assert_eq!(event.content, "hello");
+        // a retried callback is dropped by de-duplication
+        assert_eq!(webhook.receive(MESSAGE.as_bytes()).status(), StatusCode::OK);
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn body_size_limit() {
+        let body = Body::from(vec![b' '; MAX_BODY_SIZE + 1]);
+        assert!(matches!(read_body(body).await, Err(WebhookError::TooLarge)));
+        let body = Body::from(MESSAGE);
+        assert_eq!(read_body(body).await.unwrap(), MESSAGE.as_bytes());
+    }
+}